@@ -0,0 +1,84 @@
+use log::warn;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use shared::extract::ExtractOptions;
+use shared::shared::*;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+// bump whenever extraction changes in a way the parameters below don't capture
+const CACHE_VERSION: u32 = 13;
+
+// extracted edges keyed by a hash of the source file and the extraction parameters
+#[derive(Serialize, Deserialize, Default)]
+pub struct EdgeCache {
+    version: u32,
+    entries: HashMap<String, FlagEdges>,
+}
+
+impl EdgeCache {
+    pub fn new() -> EdgeCache {
+        EdgeCache {
+            version: CACHE_VERSION,
+            entries: HashMap::new(),
+        }
+    }
+
+    // a missing, unreadable or outdated cache is treated as empty
+    pub fn load<P: AsRef<Path>>(path: P) -> EdgeCache {
+        let file = match File::open(path.as_ref()) {
+            Ok(file) => file,
+            Err(_) => return EdgeCache::new(),
+        };
+        match serde_json::from_reader::<_, EdgeCache>(BufReader::new(file)) {
+            Ok(cache) if cache.version == CACHE_VERSION => cache,
+            _ => {
+                warn!("Ignoring outdated cache {}", path.as_ref().display());
+                EdgeCache::new()
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) {
+        let mut file = File::create(path).unwrap();
+        file.write_all(serde_json::to_string(self).unwrap().as_bytes()).unwrap();
+    }
+
+    pub fn get(&self, key: &str) -> Option<&FlagEdges> {
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: String, edges: FlagEdges) {
+        self.entries.insert(key, edges);
+    }
+}
+
+pub fn params_key(options: &ExtractOptions, svg_width: u32) -> String {
+    format!(
+        "{}|{}|{}|{}|{}|{}|{:?}|{}|{}|{}|{}",
+        serde_json::to_string(&options.palette).unwrap(),
+        options.metric.name(),
+        options.denom,
+        options.band,
+        options.min_run,
+        options.alpha_threshold,
+        options.complex_threshold,
+        options.profile_samples,
+        options.max_frame,
+        options.subpixel,
+        svg_width,
+    )
+}
+
+// the path is part of the key because the flag id is taken from the file name
+pub fn entry_key(params: &str, path: &Path) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(params.as_bytes());
+    hasher.update(path.to_string_lossy().as_bytes());
+    hasher.update(std::fs::read(path).unwrap());
+    format!("{:x}", hasher.finalize())
+}
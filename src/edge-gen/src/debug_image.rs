@@ -0,0 +1,76 @@
+use image::{Rgb, RgbImage};
+use shared::extract::{side_pixel_coords, ExtractOptions, SIDES};
+use shared::shared::*;
+
+use std::path::Path;
+
+const BACKGROUND: Rgb<u8> = Rgb([128, 128, 128]);
+const MARK: Rgb<u8> = Rgb([0, 0, 0]);
+const TRANSPARENT_FILL: Rgb<u8> = Rgb([192, 192, 192]);
+
+// canvas position of a point `out` pixels outside the image, beside position idx of a side
+fn margin_coords(side: Sides, idx: u32, out: u32, margin: u32, img: &RgbImage) -> (u32, u32) {
+    let (width, height) = img.dimensions();
+    match side {
+        Sides::TOP => (margin + idx, margin - 1 - out),
+        Sides::RIGHT => (margin + width + out, margin + idx),
+        Sides::BOTTOM => (margin + idx, margin + height + out),
+        Sides::LEFT => (margin - 1 - out, margin + idx),
+    }
+}
+
+// The flag is drawn with its sampled band replaced by the classified colors. In the margin
+// around it, the inner ticks mark where the classified color changes and the outer strip
+// shows the quantized edge, with a mark at each boundary between proportions.
+pub fn write_debug_image(path: &Path, img: &RgbImage, classes: &[Vec<String>], edges: &[&EdgeInfo], options: &ExtractOptions) {
+    let (width, height) = img.dimensions();
+    let margin = std::cmp::max(8, std::cmp::min(width, height) / 10);
+    let tick = margin / 4;
+
+    let mut canvas = RgbImage::from_pixel(width + 2 * margin, height + 2 * margin, BACKGROUND);
+    image::imageops::replace(&mut canvas, img, margin, margin);
+
+    let color_of = |name: &str| match name {
+        TRANSPARENT => TRANSPARENT_FILL,
+        _ => options.palette.resolve(name).map_or(MARK, |c| c.pixel()),
+    };
+
+    for (s, side) in SIDES.iter().enumerate() {
+        let side_classes = &classes[s];
+        let length = side_classes.len() as u32;
+        let depth = options.band.clamp(1, match side {
+            Sides::TOP | Sides::BOTTOM => height,
+            Sides::LEFT | Sides::RIGHT => width,
+        });
+
+        for (idx, class) in side_classes.iter().enumerate() {
+            let idx = idx as u32;
+            for d in 0..depth {
+                let (x, y) = side_pixel_coords(*side, idx, d, width, height);
+                canvas.put_pixel(x + margin, y + margin, color_of(class));
+            }
+            if idx > 0 && side_classes[idx as usize - 1] != *class {
+                for out in 0..tick {
+                    let (x, y) = margin_coords(*side, idx, out, margin, img);
+                    canvas.put_pixel(x, y, MARK);
+                }
+            }
+        }
+
+        let mut start = 0;
+        let mut cumulative = 0;
+        for (color, prop) in edges[s] {
+            cumulative += prop;
+            let end = (cumulative as f32 * length as f32 / options.denom as f32).round() as u32;
+            for idx in start..end.min(length) {
+                for out in tick + 1..margin {
+                    let (x, y) = margin_coords(*side, idx, out, margin, img);
+                    canvas.put_pixel(x, y, if idx == start && start > 0 { MARK } else { color_of(color) });
+                }
+            }
+            start = end;
+        }
+    }
+
+    canvas.save(path).unwrap();
+}
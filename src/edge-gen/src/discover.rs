@@ -0,0 +1,220 @@
+use image::Rgb;
+use log::info;
+use rayon::prelude::*;
+use shared::color::*;
+use shared::extract::{SideIterator, SIDES};
+use shared::palette::*;
+use shared::raster::open_image;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const MAX_ITERATIONS: u32 = 50;
+
+// used to give discovered clusters readable names
+const NAMED_COLORS: [(&str, [u8; 3]); 20] = [
+    ("Red", [206, 17, 38]),
+    ("Maroon", [128, 20, 45]),
+    ("Orange", [255, 136, 0]),
+    ("Gold", [215, 160, 30]),
+    ("Yellow", [252, 209, 22]),
+    ("Lime", [100, 190, 60]),
+    ("Green", [0, 122, 61]),
+    ("DarkGreen", [0, 70, 40]),
+    ("Teal", [0, 128, 128]),
+    ("Cyan", [120, 190, 235]),
+    ("Blue", [0, 90, 190]),
+    ("Navy", [0, 36, 100]),
+    ("Purple", [100, 40, 140]),
+    ("Magenta", [190, 40, 180]),
+    ("Pink", [240, 150, 180]),
+    ("Brown", [120, 70, 30]),
+    ("Tan", [200, 170, 120]),
+    ("White", [255, 255, 255]),
+    ("Grey", [128, 128, 128]),
+    ("Black", [0, 0, 0]),
+];
+
+struct ColorCount {
+    rgb: [u8; 3],
+    lab: Lab,
+    count: u32,
+}
+
+struct Cluster {
+    lab: Lab,
+    rgb: [u8; 3],
+    count: u32,
+}
+
+// transparent pixels are left out, as they never take a palette color
+fn edge_pixel_histogram(flag_files: &[PathBuf], band: u32, alpha_threshold: u8, svg_width: u32) -> Vec<ColorCount> {
+    let histogram = flag_files.par_iter().map(|flag| {
+        let mut histogram: HashMap<[u8; 3], u32> = HashMap::new();
+        let img = open_image(flag, svg_width).to_rgba8();
+        for side in &SIDES {
+            for px in SideIterator::new(*side, &img, band).flatten().filter(|px| px.0[3] >= alpha_threshold) {
+                *histogram.entry([px.0[0], px.0[1], px.0[2]]).or_insert(0) += 1;
+            }
+        }
+        histogram
+    }).reduce(HashMap::new, |mut total, part| {
+        for (rgb, count) in part {
+            *total.entry(rgb).or_insert(0) += count;
+        }
+        total
+    });
+
+    let mut colors: Vec<ColorCount> = histogram.into_iter().map(|(rgb, count)| ColorCount {
+        rgb,
+        lab: rgb_to_lab(&Rgb(rgb)),
+        count,
+    }).collect();
+    // HashMap order is random; sort so clustering is reproducible
+    colors.sort_by_key(|c| c.rgb);
+    colors
+}
+
+// splits the color set into k boxes along their widest Lab axis at the weighted median
+fn median_cut(colors: &[ColorCount], k: usize) -> Vec<Lab> {
+    let mut boxes: Vec<Vec<usize>> = vec![(0..colors.len()).collect()];
+
+    let widest_axis = |b: &Vec<usize>| {
+        let mut best = (0, 0.0);
+        for axis in 0..3 {
+            let min = b.iter().map(|&i| colors[i].lab[axis]).fold(f32::MAX, f32::min);
+            let max = b.iter().map(|&i| colors[i].lab[axis]).fold(f32::MIN, f32::max);
+            if max - min > best.1 {
+                best = (axis, max - min);
+            }
+        }
+        best
+    };
+
+    while boxes.len() < k {
+        let split = boxes.iter().enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, widest_axis(b)))
+            .max_by(|a, b| a.1.1.partial_cmp(&b.1.1).unwrap());
+        let (idx, (axis, _)) = match split {
+            Some(s) => s,
+            None => break,
+        };
+
+        let mut b = boxes.swap_remove(idx);
+        b.sort_by(|&i, &j| colors[i].lab[axis].partial_cmp(&colors[j].lab[axis]).unwrap());
+        let total: u32 = b.iter().map(|&i| colors[i].count).sum();
+        let mut acc = 0;
+        let mut cut = 1;
+        for (n, &i) in b.iter().enumerate() {
+            acc += colors[i].count;
+            if acc * 2 >= total {
+                cut = (n + 1).min(b.len() - 1);
+                break;
+            }
+        }
+        let upper = b.split_off(cut);
+        boxes.push(b);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|b| {
+        let total: f32 = b.iter().map(|&i| colors[i].count as f32).sum();
+        let mut lab = [0.0; 3];
+        for &i in b {
+            for (axis, v) in lab.iter_mut().enumerate() {
+                *v += colors[i].lab[axis] * colors[i].count as f32 / total;
+            }
+        }
+        lab
+    }).collect()
+}
+
+fn k_means(colors: &[ColorCount], k: usize) -> Vec<Cluster> {
+    let mut centers = median_cut(colors, k);
+    let mut assignment = vec![usize::MAX; colors.len()];
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (i, c) in colors.iter().enumerate() {
+            let nearest = (0..centers.len())
+                .min_by(|&a, &b| delta_e76(&c.lab, &centers[a]).partial_cmp(&delta_e76(&c.lab, &centers[b])).unwrap())
+                .unwrap();
+            if assignment[i] != nearest {
+                assignment[i] = nearest;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![([0.0f32; 3], 0.0f32); centers.len()];
+        for (i, c) in colors.iter().enumerate() {
+            let sum = &mut sums[assignment[i]];
+            for axis in 0..3 {
+                sum.0[axis] += c.lab[axis] * c.count as f32;
+            }
+            sum.1 += c.count as f32;
+        }
+        for (center, sum) in centers.iter_mut().zip(sums.iter()) {
+            if sum.1 > 0.0 {
+                *center = [sum.0[0] / sum.1, sum.0[1] / sum.1, sum.0[2] / sum.1];
+            }
+        }
+    }
+
+    // the representative RGB value is the weighted mean of the cluster's pixels
+    let mut clusters: Vec<Cluster> = centers.iter().map(|&lab| Cluster { lab, rgb: [0; 3], count: 0 }).collect();
+    let mut rgb_sums = vec![[0u64; 3]; centers.len()];
+    for (i, c) in colors.iter().enumerate() {
+        clusters[assignment[i]].count += c.count;
+        for (sum, v) in rgb_sums[assignment[i]].iter_mut().zip(c.rgb.iter()) {
+            *sum += *v as u64 * c.count as u64;
+        }
+    }
+    for (cluster, sum) in clusters.iter_mut().zip(rgb_sums.iter()) {
+        if cluster.count > 0 {
+            let n = cluster.count as u64;
+            cluster.rgb = [(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8];
+        }
+    }
+    clusters.retain(|c| c.count > 0);
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.count));
+    clusters
+}
+
+fn cluster_name(lab: &Lab, used: &mut HashMap<String, u32>) -> String {
+    let name = NAMED_COLORS.iter()
+        .min_by(|a, b| {
+            delta_e2000(lab, &rgb_to_lab(&Rgb(a.1))).partial_cmp(&delta_e2000(lab, &rgb_to_lab(&Rgb(b.1)))).unwrap()
+        })
+        .unwrap().0;
+    let n = used.entry(name.to_string()).or_insert(0);
+    *n += 1;
+    if *n == 1 {
+        name.to_string()
+    } else {
+        format!("{}{}", name, n)
+    }
+}
+
+pub fn discover_palette(flag_files: &[PathBuf], k: usize, band: u32, alpha_threshold: u8, svg_width: u32, name: &str) -> Palette {
+    let colors = edge_pixel_histogram(flag_files, band, alpha_threshold, svg_width);
+    let total: u32 = colors.iter().map(|c| c.count).sum();
+    info!("Clustering {} edge pixels ({} distinct colors) into {} colors", total, colors.len(), k);
+
+    let clusters = k_means(&colors, k);
+
+    let mut used = HashMap::new();
+    let palette_colors = clusters.iter().map(|c| {
+        let color = PaletteColor::new(&cluster_name(&c.lab, &mut used), c.rgb);
+        info!("  {:<12} {:<16} {:5.1}%", color.name, format!("{:?}", color.rgb), 100.0 * c.count as f32 / total as f32);
+        color
+    }).collect();
+
+    Palette {
+        name: name.to_string(),
+        colors: palette_colors,
+    }
+}
@@ -1,177 +1,423 @@
-use image::io::Reader as ImageReader;
-use image::Rgb;
+mod cache;
+mod debug_image;
+mod discover;
+mod overrides;
+mod quality;
+mod scales;
+mod stability;
+
+use clap::Parser;
+use glob::Pattern;
+use image::DynamicImage;
+use log::{debug, info, warn, LevelFilter};
+use rayon::prelude::*;
 use shared::shared::*;
+use shared::color::ColorMetric;
+use shared::extract::*;
+use shared::frame::trim_frame;
+use shared::metadata::load_metadata;
+use shared::palette::Palette;
+use shared::raster::{is_svg, open_image};
+use cache::*;
+use debug_image::write_debug_image;
+use overrides::*;
+use quality::*;
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
-use serde::{Serialize, Deserialize};
+fn build_flag_info(flag: PathBuf, options: &ExtractOptions, svg_width: u32, debug_dir: Option<&Path>) -> FlagEdges {
+    let id = flag.file_stem().unwrap().to_str().unwrap().to_string();
+    debug!("id: {}", id);
+    let source = open_image(&flag, svg_width);
 
-type Pixel = Rgb<u8>;
+    match debug_dir {
+        Some(dir) => {
+            let (img, trim) = trim_frame(&source.to_rgba8(), options);
+            let classes = classify_sides(&img, options);
+            let flag_edges = edges_from_classes(&id, &img, trim, &classes, options);
+            let edges = [&flag_edges.top, &flag_edges.right, &flag_edges.bottom, &flag_edges.left];
+            let rgb = DynamicImage::ImageRgba8(img).to_rgb8();
+            write_debug_image(&dir.join(format!("{}.png", id)), &rgb, &classes, &edges, options);
+            flag_edges
+        }
+        None => extract_edges(&id, &source, options),
+    }
+}
 
-const COLORS: [(&str, Pixel); 9] = [ 
-    ("Red", Rgb([255, 0, 0])),
-    ("Green", Rgb([0, 255, 0])),
-    ("Blue", Rgb([0, 0, 255])),
-    ("Yellow", Rgb([255, 255, 0])),
-    ("Cyan", Rgb([0, 255, 255])),
-    ("Magenta", Rgb([255, 0, 255])),
-    ("Orange", Rgb([255, 128, 0])),
-    ("White", Rgb([255, 255, 255])),
-    ("Black", Rgb([0, 0, 0])),
-];
+const IMAGE_EXTENSIONS: [&str; 7] = ["png", "svg", "jpg", "jpeg", "gif", "bmp", "webp"];
 
-const PROPORTION_DENOM: u32 = 24;
+#[derive(Parser, Debug)]
+#[command(about = "Extracts quantized edge colors from flag images")]
+struct Args {
+    /// Flag image files or directories containing them
+    #[arg(default_value = "./flags/")]
+    inputs: Vec<PathBuf>,
 
-const BLACK_THRESH: u8 = 75;
+    /// Only read files whose name or path matches one of these globs
+    #[arg(long)]
+    include: Vec<String>,
 
-struct SideIterator<'a> {
-    side: Sides,
-    idx: u32,
-    img: &'a image::ImageBuffer<image::Rgb<u8>, std::vec::Vec<u8>>,
-}
+    /// Skip files whose name or path matches one of these globs
+    #[arg(long)]
+    exclude: Vec<String>,
 
-#[derive(Serialize, Deserialize, Debug)]
-struct FlagEdges {
-    id: String,
-    top: EdgeInfo,
-    right: EdgeInfo,
-    bottom: EdgeInfo,
-    left: EdgeInfo,
-}
+    /// Where to write the edge data
+    #[arg(short, long, default_value = "flag_edges.json")]
+    output: PathBuf,
 
-impl SideIterator<'_> {
-    pub fn new(side: Sides, img: &image::ImageBuffer<Pixel, Vec<u8>>) -> SideIterator {
-        SideIterator {
-            side,
-            idx: 0,
-            img,
-        }
-    }
+    /// Number of units each edge is divided into
+    #[arg(long, default_value_t = 24, value_parser = clap::value_parser!(u32).range(1..))]
+    denom: u32,
 
-    pub fn length(&self) -> u32 {
-        match self.side {
-            Sides::TOP | Sides::BOTTOM => self.img.width(),
-            Sides::LEFT | Sides::RIGHT => self.img.height(),
-        }
-    }
+    /// Palette file (TOML or JSON); the built-in palette is used if omitted
+    #[arg(long)]
+    palette: Option<String>,
+
+    /// Color distance: rgb, cie76 or ciede2000
+    #[arg(long, default_value = "ciede2000")]
+    metric: ColorMetric,
+
+    /// Number of pixels sampled in from each edge
+    #[arg(long, default_value_t = 1)]
+    band: u32,
+
+    /// Runs shorter than this many units are merged into a neighbour
+    #[arg(long, default_value_t = 0.5)]
+    min_run: f32,
+
+    /// Pixels with alpha below this are classed as Transparent; 0 disables this
+    #[arg(long, default_value_t = 128)]
+    alpha_threshold: u8,
+
+    /// Width SVG flags are rasterized at
+    #[arg(long, default_value_t = 1200)]
+    svg_width: u32,
+
+    /// Mark edges whose complexity score exceeds this as complex
+    #[arg(long)]
+    complex_threshold: Option<f32>,
+
+    /// Number of Lab samples each side's profile is resampled to
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..))]
+    profile_samples: u32,
+
+    /// Widest frame or whitespace padding, in pixels, trimmed before sampling; 0 disables this
+    #[arg(long, default_value_t = 4)]
+    max_frame: u32,
+
+    /// Measure run boundaries in whole pixels instead of to a fraction of a pixel
+    #[arg(long)]
+    no_subpixel: bool,
+
+    /// Warn about flags whose shorter side is below this many pixels
+    #[arg(long, default_value_t = 96)]
+    min_size: u32,
+
+    /// Warn about edges where more than this share of the side is runs too short to quantize
+    #[arg(long, default_value_t = 0.15)]
+    max_noise: f32,
+
+    /// Warn about edges whose pixels match their classified colors less often than this
+    #[arg(long, default_value_t = 0.85)]
+    min_confidence: f32,
+
+    /// Fail without writing the edge file if more than this many flags fail the quality checks
+    #[arg(long)]
+    max_poor: Option<usize>,
+
+    /// Re-extract every flag at these widths, report signatures that change, and exit
+    #[arg(long, value_delimiter = ',')]
+    check_scales: Vec<u32>,
+
+    /// Extract every flag under a sweep of palettes, bands and denominators, report flags whose
+    /// edges or possible neighbours change, and exit
+    #[arg(long)]
+    stability: bool,
+
+    /// Palette files swept by --stability, alongside the one in use
+    #[arg(long, value_delimiter = ',')]
+    sweep_palettes: Vec<PathBuf>,
+
+    /// Band depths swept by --stability
+    #[arg(long, value_delimiter = ',', default_value = "1,3")]
+    sweep_bands: Vec<u32>,
+
+    /// Denominators swept by --stability
+    #[arg(long, value_delimiter = ',', default_value = "12,24,48", value_parser = clap::value_parser!(u32).range(1..))]
+    sweep_denoms: Vec<u32>,
+
+    /// With --stability, also report which adjacencies of this best_graph_found file hold
+    /// under each setting, matched the way the graph records; multi_flags.json is read from the
+    /// same directory
+    #[arg(long)]
+    check_graph: Option<PathBuf>,
+
+    /// Cluster the edge pixels into this many colors and use the result as the palette
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    discover: Option<usize>,
+
+    /// Where to write a discovered palette
+    #[arg(long, default_value = "palettes/discovered.toml")]
+    palette_out: String,
+
+    /// Names, regions and tags attached to each flag id (TOML or JSON)
+    #[arg(long, default_value = "metadata/flags.toml")]
+    metadata: PathBuf,
+
+    /// Curated corrections applied to the extracted edges; skipped if the file doesn't exist
+    #[arg(long, default_value = "metadata/overrides.toml")]
+    overrides: PathBuf,
+
+    /// Cache of previously extracted edges, keyed by file content and parameters
+    #[arg(long, default_value = ".edge-cache.json")]
+    cache: PathBuf,
+
+    /// Re-extract every flag and leave the cache untouched
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Write an image per flag showing how its edges were classified; bypasses the cache
+    #[arg(long)]
+    debug_dir: Option<PathBuf>,
+
+    /// Number of worker threads; defaults to one per CPU
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Only print warnings and errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print every flag as it is processed, and the extracted edges
+    #[arg(short, long)]
+    verbose: bool,
 }
 
-impl Iterator for SideIterator<'_> {
-    type Item = Pixel;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let idx = self.idx;
-        self.idx += 1;
-        
-        match self.side {
-            Sides::TOP => {
-                if idx < self.img.width() {
-                    Some(*self.img.get_pixel(idx, 0))
-                } else {
-                    None
-                }
-            },
-            Sides::RIGHT => {
-                if idx < self.img.height() {
-                    Some(*self.img.get_pixel(self.img.width() - 1, idx))
-                } else {
-                    None
-                }
-            },
-            Sides::BOTTOM => {
-                if idx < self.img.width() {
-                    Some(*self.img.get_pixel(idx, self.img.height() - 1))
-                } else {
-                    None
-                }
-            },
-            Sides::LEFT => {
-                if idx < self.img.height() {
-                    Some(*self.img.get_pixel(0, idx))
-                } else {
-                    None
+fn glob_matches(patterns: &[Pattern], path: &Path) -> bool {
+    let name = path.file_name().unwrap().to_str().unwrap();
+    patterns.iter().any(|p| p.matches(name) || p.matches_path(path))
+}
+
+fn collect_flag_files(args: &Args) -> Vec<PathBuf> {
+    let include: Vec<Pattern> = args.include.iter().map(|p| Pattern::new(p).unwrap()).collect();
+    let exclude: Vec<Pattern> = args.exclude.iter().map(|p| Pattern::new(p).unwrap()).collect();
+
+    let mut candidates = Vec::new();
+    for input in &args.inputs {
+        if input.is_dir() {
+            for entry in std::fs::read_dir(input).unwrap() {
+                let path = entry.unwrap().path();
+                let is_image = path.extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+                if path.is_file() && is_image {
+                    candidates.push(path);
                 }
             }
+        } else {
+            candidates.push(input.clone());
         }
     }
-}
 
-fn nearest_color(color: &Pixel) -> String {
-    let mut best = (COLORS[0], -1.0);
-    let not_black = color[0] > BLACK_THRESH || color[1] > BLACK_THRESH || color[2] > BLACK_THRESH;
-    for c in &COLORS {
-        let mut d_sq = 0.0;
-        for i in 0..3 {
-            d_sq += ((color[i] as f32) - (c.1[i] as f32)).powi(2);
-        }
-        if c.0 == "Black" {
-            if (best.1 == -1.0 || d_sq < best.1) && !not_black {
-                best = (*c, d_sq);
-            }
-        } else if best.1 == -1.0 || d_sq < best.1 {
-            best = (*c, d_sq);
+    candidates.retain(|path| {
+        (include.is_empty() || glob_matches(&include, path)) && !glob_matches(&exclude, path)
+    });
+
+    // one file per id; like the renderer, a flag's svg is preferred over its raster files
+    candidates.sort_by_key(|path| (!is_svg(path), path.clone()));
+    let mut ids = HashSet::new();
+    candidates.retain(|path| {
+        let id = path.file_stem().unwrap().to_str().unwrap().to_string();
+        let first = ids.insert(id.clone());
+        if !first {
+            debug!("Skipping {}, another file already provides {}", path.display(), id);
         }
-    }
-    String::from(best.0.0)
+        first
+    });
+    candidates
 }
 
-fn build_side_info(iter: &mut SideIterator) -> EdgeInfo {
-    let mut output = Vec::new();
-    let mut current_count = 1;
-    let nx = iter.next().unwrap();
-    let mut current_color = nearest_color(&nx);
-    let iter_length = iter.length();
+fn main() {
+    let args = Args::parse();
 
-    let to_prop = |count: u32| {
-        ((count * PROPORTION_DENOM) as f32 / (iter_length as f32)).round() as u32
+    let level = if args.quiet {
+        LevelFilter::Warn
+    } else if args.verbose {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
     };
+    env_logger::Builder::new().filter_level(level).format_target(false).format_timestamp(None).init();
+
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global().unwrap();
+    }
 
-    let push_value = |current_color: String, current_count: u32, output: &mut Vec<(String, u32)>| {
-        let prop = to_prop(current_count);
-        if prop > 0 {
-            output.push((current_color, prop));
+    let flag_files = collect_flag_files(&args);
+    info!("Found {} flag images", flag_files.len());
+
+    let mut palette_file = args.palette.clone();
+    if let Some(k) = args.discover {
+        let name = Path::new(&args.palette_out).file_stem().unwrap().to_str().unwrap().to_string();
+        let palette = discover::discover_palette(&flag_files, k, args.band, args.alpha_threshold, args.svg_width, &name);
+        palette.save(&args.palette_out);
+        info!("Wrote discovered palette to {}", args.palette_out);
+        palette_file = Some(args.palette_out.clone());
+    }
+    let palette = match &palette_file {
+        Some(path) => Palette::load(path),
+        None => Palette::builtin(),
+    };
+    info!("Using color metric: {}", args.metric.name());
+    info!("Using palette: {} ({} colors)", palette.name, palette.colors.len());
+    let overrides = if args.overrides.exists() {
+        match load_overrides(&args.overrides) {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                eprintln!("Invalid overrides: {}", e);
+                std::process::exit(1);
+            }
         }
+    } else {
+        Vec::new()
     };
+    info!("Sampling {} pixel(s) in from each edge", args.band);
 
-    for px in iter {
-        let col = nearest_color(&px);
-        if col == current_color {
-            current_count += 1;
-        } else {
-            push_value(current_color, current_count, &mut output);
-            current_count = 1;
-            current_color = col;
-        }
+    let source = EdgeSource {
+        palette: palette.name.clone(),
+        palette_file,
+        metric: args.metric.name().to_string(),
+        denom: args.denom,
+        band: args.band,
+        min_run: args.min_run,
+        alpha_threshold: args.alpha_threshold,
+        complex_threshold: args.complex_threshold,
+        profile_samples: args.profile_samples,
+        max_frame: args.max_frame,
+        subpixel: !args.no_subpixel,
+        classes: palette.classes(),
+    };
+    let options = ExtractOptions {
+        palette,
+        metric: args.metric,
+        denom: args.denom,
+        band: args.band,
+        min_run: args.min_run,
+        alpha_threshold: args.alpha_threshold,
+        complex_threshold: args.complex_threshold,
+        profile_samples: args.profile_samples,
+        max_frame: args.max_frame,
+        subpixel: !args.no_subpixel,
+    };
+
+    if !args.check_scales.is_empty() {
+        scales::check_scales(&flag_files, &args.check_scales, &options, args.svg_width);
+        return;
+    }
+    if args.stability {
+        let graph = args.check_graph.as_ref().map(|path| match stability::load_graph(path) {
+            Ok(graph) => graph,
+            Err(e) => {
+                eprintln!("Cannot check the graph: {}", e);
+                std::process::exit(1);
+            }
+        });
+        let mut palettes = vec![options.palette.clone()];
+        palettes.extend(args.sweep_palettes.iter().map(Palette::load));
+        stability::check_stability(&flag_files, &options, args.svg_width, &palettes, &args.sweep_bands, &args.sweep_denoms, graph.as_ref());
+        return;
     }
-    push_value(current_color, current_count, &mut output);
-    output
-}
 
-fn build_flag_info(flag: std::path::PathBuf) -> FlagEdges {
-    let id = flag.file_stem().unwrap().to_str().unwrap().to_string();
-    println!("id: {}", id);
-    let img = ImageReader::open(flag).unwrap().decode().unwrap().to_rgb8();
-
-    FlagEdges {
-        id,
-        top: build_side_info(&mut SideIterator::new(Sides::TOP, &img)),
-        right: build_side_info(&mut SideIterator::new(Sides::RIGHT, &img)),
-        bottom: build_side_info(&mut SideIterator::new(Sides::BOTTOM, &img)),
-        left: build_side_info(&mut SideIterator::new(Sides::LEFT, &img)),
+    if let Some(dir) = &args.debug_dir {
+        std::fs::create_dir_all(dir).unwrap();
     }
-}
+    let cache = if args.no_cache {
+        EdgeCache::new()
+    } else {
+        EdgeCache::load(&args.cache)
+    };
+    let params = params_key(&options, args.svg_width);
 
-fn main() {
+    let mut results: Vec<(String, FlagEdges, bool)> = flag_files.into_par_iter().map(|path| {
+        let key = entry_key(&params, &path);
+        match cache.get(&key) {
+            Some(edges) if args.debug_dir.is_none() => (key, edges.clone(), true),
+            _ => (key, build_flag_info(path, &options, args.svg_width, args.debug_dir.as_deref()), false),
+        }
+    }).collect();
+    results.sort_by(|a, b| a.1.id.cmp(&b.1.id));
+
+    let reused = results.iter().filter(|r| r.2).count();
+    info!("Extracted {} flags, reused {} from cache", results.len() - reused, reused);
+
+    let metadata = if args.metadata.exists() {
+        load_metadata(&args.metadata)
+    } else {
+        warn!("No metadata file at {}, flags will only be known by id", args.metadata.display());
+        HashMap::new()
+    };
+
+    // only entries for the current inputs are kept; metadata is attached afterwards so
+    // editing it never invalidates the cache
+    let mut new_cache = EdgeCache::new();
     let mut output_data: Vec<FlagEdges> = Vec::new();
+    let mut missing = Vec::new();
+    for (key, mut edges, _) in results {
+        new_cache.insert(key, edges.clone());
+        edges.metadata = metadata.get(&edges.id).cloned();
+        if edges.metadata.is_none() {
+            missing.push(edges.id.clone());
+        }
+        output_data.push(edges);
+    }
+    if !metadata.is_empty() && !missing.is_empty() {
+        warn!("No metadata for {}", missing.join(", "));
+    }
+    let limits = QualityLimits {
+        min_size: args.min_size,
+        max_noise: args.max_noise,
+        min_confidence: args.min_confidence,
+    };
+    let poor = check_quality(&output_data, &limits, &options);
+    if !overrides.is_empty() {
+        apply_overrides(&mut output_data, &overrides, &options.palette, options.denom);
+    }
+    let trimmed: Vec<String> = output_data.iter()
+        .filter(|f| SIDES.iter().any(|s| *f.trim.side(*s) > 0))
+        .map(|f| format!("{} ({} {} {} {})", f.id, f.trim.top, f.trim.right, f.trim.bottom, f.trim.left))
+        .collect();
+    if !trimmed.is_empty() {
+        info!("Trimmed a frame or padding (top right bottom left) from {}", trimmed.join(", "));
+    }
+    if let Some(threshold) = args.complex_threshold {
+        let complex: Vec<String> = output_data.iter()
+            .flat_map(|f| SIDES.iter().filter(move |s| f.complexity.side(**s).complex).map(move |s| format!("{} {:?}", f.id, s)))
+            .collect();
+        info!("{} edges score above {} and are marked complex", complex.len(), threshold);
+        debug!("Complex edges: {}", complex.join(", "));
+    }
+    if !args.no_cache {
+        new_cache.save(&args.cache);
+    }
 
-    let flag_files = std::fs::read_dir("./flags/").unwrap();
-    for path in flag_files {
-        output_data.push(build_flag_info(path.unwrap().path()));
+    if let Some(max_poor) = args.max_poor {
+        if poor > max_poor {
+            eprintln!("{} flags failed the quality checks, more than --max-poor {} allows; not writing {}", poor, max_poor, args.output.display());
+            std::process::exit(1);
+        }
     }
 
-    let mut output_file = File::create("flag_edges.json").unwrap();
-    output_file.write_all(serde_json::to_string(&output_data).unwrap().as_bytes()).unwrap();
+    let output = EdgeFile {
+        source,
+        flags: output_data,
+    };
+
+    let mut output_file = File::create(&args.output).unwrap();
+    output_file.write_all(serde_json::to_string(&output).unwrap().as_bytes()).unwrap();
+    info!("Wrote {} flags to {}", output.flags.len(), args.output.display());
 
-    println!("{:?}", output_data);
+    debug!("{:?}", output.flags);
 }
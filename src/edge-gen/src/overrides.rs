@@ -0,0 +1,188 @@
+use log::{info, warn};
+use serde::Deserialize;
+use shared::extract::SIDES;
+use shared::palette::Palette;
+use shared::shared::*;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::ops::AddAssign;
+use std::path::Path;
+
+// a curated correction to one flag, applied to every side unless a side is given
+#[derive(Deserialize, Debug)]
+pub struct Override {
+    pub flag: String,
+    pub side: Option<String>,
+    // replaces the side's edge outright; needs a side and must sum to the denominator
+    pub edge: Option<EdgeInfo>,
+    // renames colors along the affected sides, joining runs that become the same color
+    #[serde(default)]
+    pub remap: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct OverrideFile {
+    #[serde(rename = "override", default)]
+    overrides: Vec<Override>,
+}
+
+// checked up front so a bad entry is reported before any flag is extracted
+pub fn load_overrides<P: AsRef<Path>>(path: P) -> Result<Vec<Override>, String> {
+    let path = path.as_ref();
+    let mut contents = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut contents)).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+
+    let file: OverrideFile = toml::from_str(&contents).map_err(|e| format!("cannot parse {}: {}", path.display(), e))?;
+    for o in &file.overrides {
+        if o.edge.is_none() && o.remap.is_empty() {
+            return Err(format!("override for {} in {} has neither an edge nor a remap", o.flag, path.display()));
+        }
+        if o.edge.is_some() && o.side.is_none() {
+            return Err(format!("override for {} in {} replaces an edge without naming its side", o.flag, path.display()));
+        }
+        if let Some(side) = &o.side {
+            side.parse::<Sides>().map_err(|e| format!("override for {} in {}: {}", o.flag, path.display(), e))?;
+        }
+    }
+    Ok(file.overrides)
+}
+
+fn remap_edge<T: Copy + AddAssign>(edge: &[(String, T)], remap: &HashMap<String, String>) -> Vec<(String, T)> {
+    let mut remapped: Vec<(String, T)> = Vec::new();
+    for (color, prop) in edge {
+        let color = remap.get(color).unwrap_or(color);
+        match remapped.last_mut() {
+            Some(last) if last.0 == *color => last.1 += *prop,
+            _ => remapped.push((color.clone(), *prop)),
+        }
+    }
+    remapped
+}
+
+// overrides that no longer change anything are reported, as the extraction has probably caught up;
+// the raw runs are corrected along with the edge so re-quantizing keeps the override
+pub fn apply_overrides(flags: &mut [FlagEdges], overrides: &[Override], palette: &Palette, denom: u32) {
+    let mut applied = 0;
+    for o in overrides {
+        let flag = match flags.iter_mut().find(|f| f.id == o.flag) {
+            Some(flag) => flag,
+            None => {
+                warn!("Override for {} matches no flag among the inputs", o.flag);
+                continue;
+            }
+        };
+        let sides = match &o.side {
+            Some(side) => vec![side.parse::<Sides>().unwrap()],
+            None => SIDES.to_vec(),
+        };
+        let target = match &o.side {
+            Some(side) => format!("{} {}", o.flag, side),
+            None => o.flag.clone(),
+        };
+
+        for to in o.remap.values() {
+            if to != TRANSPARENT && palette.resolve(to).is_none() {
+                warn!("Override for {} remaps to {}, which is not in palette {}", target, to, palette.name);
+            }
+        }
+        if let Some(edge) = &o.edge {
+            let total: u32 = edge.iter().map(|e| e.1).sum();
+            if total != denom {
+                warn!("Skipping override for {}: its edge sums to {}, not {}", target, total, denom);
+                continue;
+            }
+        }
+
+        let mut changed = false;
+        for side in sides {
+            let current = flag.edge_mut(side);
+            let mut edge = remap_edge(current, &o.remap);
+            if let Some(replacement) = &o.edge {
+                edge = replacement.clone();
+            }
+            if edge != *current {
+                *current = edge;
+                changed = true;
+            }
+
+            let runs = flag.runs.side_mut(side);
+            *runs = remap_edge(runs, &o.remap);
+            if let Some(replacement) = &o.edge {
+                let length: f32 = runs.iter().map(|r| r.1).sum();
+                *runs = replacement.iter().map(|(color, prop)| (color.clone(), *prop as f32 * length / denom as f32)).collect();
+            }
+        }
+        if changed {
+            applied += 1;
+        } else {
+            warn!("Override for {} no longer changes anything", target);
+        }
+    }
+    info!("Applied {} of {} overrides", applied, overrides.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use shared::extract::{extract_edges, ExtractOptions};
+
+    fn edge(units: &[(&str, u32)]) -> EdgeInfo {
+        units.iter().map(|(color, units)| (color.to_string(), *units)).collect()
+    }
+
+    // a vertical blue, white and red tricolour
+    fn tricolour(options: &ExtractOptions) -> FlagEdges {
+        let img = RgbaImage::from_fn(60, 40, |x, _| match x {
+            0..=19 => Rgba([0, 70, 170, 255]),
+            20..=39 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([206, 17, 38, 255]),
+        });
+        extract_edges("tc", &DynamicImage::ImageRgba8(img), options)
+    }
+
+    fn apply(flag: &mut FlagEdges, o: Override, options: &ExtractOptions) {
+        apply_overrides(std::slice::from_mut(flag), &[o], &options.palette, options.denom);
+    }
+
+    #[test]
+    fn remap_joins_adjacent_runs() {
+        let options = ExtractOptions::default();
+        let mut flag = tricolour(&options);
+        assert_eq!(flag.top, edge(&[("Blue", 8), ("White", 8), ("Red", 8)]));
+        apply(&mut flag, Override {
+            flag: "tc".to_string(),
+            side: Some("top".to_string()),
+            edge: None,
+            remap: HashMap::from([("White".to_string(), "Blue".to_string())]),
+        }, &options);
+
+        assert_eq!(flag.top, edge(&[("Blue", 16), ("Red", 8)]));
+        assert_eq!(flag.bottom, edge(&[("Blue", 8), ("White", 8), ("Red", 8)]));
+        assert_eq!(flag.runs.top.iter().map(|r| r.0.as_str()).collect::<Vec<&str>>(), vec!["Blue", "Red"]);
+        flag.requantize(Some(12), options.min_run, false);
+        assert_eq!(flag.top, edge(&[("Blue", 8), ("Red", 4)]));
+    }
+
+    #[test]
+    fn replaced_edges_survive_requantizing() {
+        let options = ExtractOptions::default();
+        let mut flag = tricolour(&options);
+        apply(&mut flag, Override {
+            flag: "tc".to_string(),
+            side: Some("left".to_string()),
+            edge: Some(edge(&[("Red", 18), ("Blue", 6)])),
+            remap: HashMap::new(),
+        }, &options);
+
+        assert_eq!(flag.left, edge(&[("Red", 18), ("Blue", 6)]));
+        let length: f32 = flag.runs.left.iter().map(|r| r.1).sum();
+        assert!((length - 40.0).abs() < 1e-4);
+        flag.requantize(Some(12), options.min_run, false);
+        assert_eq!(flag.left, edge(&[("Red", 9), ("Blue", 3)]));
+        flag.requantize(Some(48), options.min_run, false);
+        assert_eq!(flag.left, edge(&[("Red", 36), ("Blue", 12)]));
+    }
+}
@@ -0,0 +1,53 @@
+use log::{info, warn};
+use shared::extract::{ExtractOptions, SIDES};
+use shared::runs::Run;
+use shared::shared::*;
+
+// below these a flag's edges are reported as unreliable
+pub struct QualityLimits {
+    pub min_size: u32,
+    pub max_noise: f32,
+    pub min_confidence: f32,
+}
+
+// share of a side covered by runs too short to survive quantization, such as compression
+// artifacts or antialiasing; detailed emblems score high as well, though rarely above 0.12
+fn edge_noise(runs: &[Run], options: &ExtractOptions) -> f32 {
+    let length: f32 = runs.iter().map(|r| r.1).sum();
+    let shortest = length * options.min_run / options.denom as f32;
+    runs.iter().filter(|r| r.1 < shortest).map(|r| r.1).sum::<f32>() / length
+}
+
+fn problems(flag: &FlagEdges, limits: &QualityLimits, options: &ExtractOptions) -> Vec<String> {
+    let mut problems = Vec::new();
+    let width = flag.width - flag.trim.left - flag.trim.right;
+    let height = flag.height - flag.trim.top - flag.trim.bottom;
+    if width.min(height) < limits.min_size {
+        problems.push(format!("{}x{} px is below {} px", width, height, limits.min_size));
+    }
+    for side in SIDES {
+        let noise = edge_noise(flag.runs.side(side), options);
+        if noise > limits.max_noise {
+            problems.push(format!("{:.0}% of the {} edge is stray pixels", noise * 100.0, side.name()));
+        }
+        let confidence = flag.complexity.side(side).confidence;
+        if confidence < limits.min_confidence {
+            problems.push(format!("{} edge classified with {:.2} confidence", side.name(), confidence));
+        }
+    }
+    problems
+}
+
+// warns about every flag that fails a check and returns how many did
+pub fn check_quality(flags: &[FlagEdges], limits: &QualityLimits, options: &ExtractOptions) -> usize {
+    let mut poor = 0;
+    for flag in flags {
+        let problems = problems(flag, limits, options);
+        if !problems.is_empty() {
+            warn!("{} may have unreliable edges: {}", flag.id, problems.join("; "));
+            poor += 1;
+        }
+    }
+    info!("{} of {} flags passed the quality checks", flags.len() - poor, flags.len());
+    poor
+}
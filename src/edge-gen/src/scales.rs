@@ -0,0 +1,53 @@
+use image::imageops::FilterType;
+use image::GenericImageView;
+use log::info;
+use rayon::prelude::*;
+use shared::extract::{extract_edges, ExtractOptions, SIDES};
+use shared::raster::{is_svg, open_image};
+use shared::shared::*;
+
+use std::path::PathBuf;
+
+fn describe(edge: &EdgeInfo) -> String {
+    edge.iter().map(|(color, prop)| format!("{}{}", color, prop)).collect::<Vec<String>>().join(" ")
+}
+
+// Extracts every flag again at each width and lists the sides whose quantized edge differs
+// from the one read at the file's own resolution. SVGs are rasterized at each width;
+// other images are resampled.
+pub fn check_scales(flag_files: &[PathBuf], widths: &[u32], options: &ExtractOptions, svg_width: u32) {
+    info!("Checking {} flags at widths {:?}", flag_files.len(), widths);
+
+    let mut results: Vec<(String, Vec<String>)> = flag_files.par_iter().map(|path| {
+        let id = path.file_stem().unwrap().to_str().unwrap().to_string();
+        let source = open_image(path, svg_width);
+        let native = extract_edges(&id, &source, options);
+
+        let mut changes = Vec::new();
+        for &width in widths {
+            let img = if is_svg(path) {
+                open_image(path, width)
+            } else {
+                let height = ((width as f32 * source.height() as f32 / source.width() as f32).round() as u32).max(1);
+                source.resize_exact(width, height, FilterType::Triangle)
+            };
+            let scaled = extract_edges(&id, &img, options);
+            for side in SIDES {
+                if scaled.edge(side) != native.edge(side) {
+                    changes.push(format!("{}px {}: {} -> {}", width, side.name(), describe(native.edge(side)), describe(scaled.edge(side))));
+                }
+            }
+        }
+        (id, changes)
+    }).collect();
+    results.sort();
+
+    let changed: Vec<&(String, Vec<String>)> = results.iter().filter(|r| !r.1.is_empty()).collect();
+    for (id, changes) in &changed {
+        info!("{}:", id);
+        for change in changes {
+            info!("  {}", change);
+        }
+    }
+    info!("{} of {} flags have a signature that depends on resolution", changed.len(), results.len());
+}
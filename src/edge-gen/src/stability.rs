@@ -0,0 +1,307 @@
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use shared::extract::{extract_edges, ExtractOptions, SIDES};
+use shared::matching::MatchSettings;
+use shared::palette::Palette;
+use shared::raster::open_image;
+use shared::shared::*;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+// a flag's four edges, in SIDES order
+type Signature = Vec<EdgeInfo>;
+
+// a flag's four edges as the matcher compared them, with whether each was marked complex
+type Placed = Vec<(EdgeInfo, bool)>;
+
+// one combination of palette, band and denominator
+struct Setting {
+    label: String,
+    denom: u32,
+    classes: HashMap<String, String>,
+}
+
+// an arrangement saved by the matcher, with the flags each of its cells stands for
+pub struct SavedGraph {
+    path: PathBuf,
+    graph: FlagGraph,
+    matching: MatchSettings,
+    multi_flags: HashMap<String, HashSet<String>>,
+}
+
+// read before the sweep, so a missing or malformed file is reported straight away
+pub fn load_graph(path: &Path) -> Result<SavedGraph, String> {
+    let read = |path: &Path| File::open(path).map(BufReader::new).map_err(|e| format!("cannot read {}: {}", path.display(), e));
+    let serialized: FlagGraphSerialize = serde_json::from_reader(read(path)?)
+        .map_err(|e| format!("cannot parse {}: {}", path.display(), e))?;
+    let multi_flags_path = path.with_file_name("multi_flags.json");
+    let multi_flags: HashMap<String, HashSet<String>> = serde_json::from_reader(read(&multi_flags_path)?)
+        .map_err(|e| format!("cannot parse {}: {}", multi_flags_path.display(), e))?;
+
+    let graph = serialized.to_flag_graph();
+    if let Some(cell) = graph.graph.values().find(|cell| !multi_flags.contains_key(*cell)) {
+        return Err(format!("{} places {}, which is not in {}", path.display(), cell, multi_flags_path.display()));
+    }
+    Ok(SavedGraph {
+        path: path.to_path_buf(),
+        graph,
+        matching: serialized.matching,
+        multi_flags,
+    })
+}
+
+fn side_index(side: Sides) -> usize {
+    SIDES.iter().position(|s| s.name() == side.name()).unwrap()
+}
+
+fn describe(edge: &EdgeInfo) -> String {
+    edge.iter().map(|(color, prop)| format!("{}{}", color, prop)).collect::<Vec<String>>().join(" ")
+}
+
+// the same colors in the same proportions, whatever the two denominators
+fn same_edge(a: &EdgeInfo, a_denom: u32, b: &EdgeInfo, b_denom: u32) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.0 == y.0 && x.1 * b_denom == y.1 * a_denom)
+}
+
+// which flags could be placed against each side of every flag, per side in SIDES order
+fn partners(signatures: &HashMap<String, Signature>) -> HashMap<String, Vec<BTreeSet<String>>> {
+    let mut by_edge: HashMap<(usize, &EdgeInfo), Vec<&str>> = HashMap::new();
+    for (id, signature) in signatures {
+        for (i, edge) in signature.iter().enumerate() {
+            by_edge.entry((i, edge)).or_default().push(id);
+        }
+    }
+    signatures.iter().map(|(id, signature)| {
+        let sides = SIDES.iter().enumerate().map(|(i, side)| {
+            by_edge.get(&(side_index(side.opposite()), &signature[i]))
+                .map(|ids| ids.iter().filter(|other| **other != id).map(|other| other.to_string()).collect())
+                .unwrap_or_default()
+        }).collect();
+        (id.clone(), sides)
+    }).collect()
+}
+
+fn join(ids: &BTreeSet<String>) -> String {
+    ids.iter().cloned().collect::<Vec<String>>().join(", ")
+}
+
+// Extracts every flag under each combination of palette, band and denominator and reports the
+// flags whose edges or possible neighbours differ from those under the current settings. Edges
+// count as the same at another denominator when their proportions are. Each palette and band
+// is extracted once; denominators are swept by re-quantizing the raw runs.
+pub fn check_stability(flag_files: &[PathBuf], options: &ExtractOptions, svg_width: u32, palettes: &[Palette], bands: &[u32], denoms: &[u32], graph: Option<&SavedGraph>) {
+    let mut extractions = vec![options.clone()];
+    for palette in palettes {
+        for &band in bands {
+            extractions.push(ExtractOptions {
+                palette: palette.clone(),
+                band,
+                ..options.clone()
+            });
+        }
+    }
+    let mut settings = vec![Setting {
+        label: String::from("current settings"),
+        denom: options.denom,
+        classes: options.palette.classes(),
+    }];
+    for extraction in &extractions[1..] {
+        for &denom in denoms {
+            settings.push(Setting {
+                label: format!("palette {}, band {}, denom {}", extraction.palette.name, extraction.band, denom),
+                denom,
+                classes: extraction.palette.classes(),
+            });
+        }
+    }
+    info!("Checking {} flags under {} settings", flag_files.len(), settings.len() - 1);
+
+    // signatures of every flag under each setting, the current settings first, and with a
+    // graph to check, its edges as the matcher would have compared them
+    let extracted: Vec<(String, Vec<Signature>, Vec<Placed>)> = flag_files.par_iter().map(|path| {
+        let id = path.file_stem().unwrap().to_str().unwrap().to_string();
+        let img = open_image(path, svg_width);
+        let mut signatures = Vec::new();
+        let mut placed = Vec::new();
+        for (i, extraction) in extractions.iter().enumerate() {
+            let edges = extract_edges(&id, &img, extraction);
+            let swept: Vec<Option<u32>> = if i == 0 { vec![None] } else { denoms.iter().map(|d| Some(*d)).collect() };
+            for denom in swept {
+                let mut flag = edges.clone();
+                if denom.is_some() {
+                    flag.requantize(denom, extraction.min_run, false);
+                }
+                signatures.push(SIDES.iter().map(|s| flag.edge(*s).clone()).collect());
+                if let Some(graph) = graph {
+                    // the graph's denominator stands in for the current one; swept ones are kept
+                    let matching = MatchSettings {
+                        denom: if i == 0 { graph.matching.denom } else { None },
+                        ..graph.matching.clone()
+                    };
+                    matching.requantize(&mut flag, extraction.min_run);
+                    placed.push(SIDES.iter().map(|s| (flag.edge(*s).clone(), flag.complexity.side(*s).complex)).collect());
+                }
+            }
+        }
+        (id, signatures, placed)
+    }).collect();
+
+    let signatures: Vec<HashMap<String, Signature>> = (0..settings.len())
+        .map(|i| extracted.iter().map(|(id, signatures, _)| (id.clone(), signatures[i].clone())).collect())
+        .collect();
+    let partners: Vec<HashMap<String, Vec<BTreeSet<String>>>> = signatures.iter().map(partners).collect();
+
+    let mut ids: Vec<&String> = partners[0].keys().collect();
+    ids.sort();
+    let mut unstable = 0;
+    let mut edges_per_setting = vec![0; settings.len()];
+    let mut partners_per_setting = vec![0; settings.len()];
+    for id in ids {
+        let mut changes = Vec::new();
+        let mut edges_changed = BTreeSet::new();
+        let mut partners_changed = BTreeSet::new();
+        let mut settings_changed = 0;
+        for (s, setting) in settings.iter().enumerate().skip(1) {
+            let (mut edge_changed, mut partner_changed) = (false, false);
+            for (i, side) in SIDES.iter().enumerate() {
+                let (edge_before, edge_after) = (&signatures[0][id][i], &signatures[s][id][i]);
+                let (before, after) = (&partners[0][id][i], &partners[s][id][i]);
+                let same = same_edge(edge_before, settings[0].denom, edge_after, setting.denom);
+                if same && before == after {
+                    continue;
+                }
+                let mut change = format!("  {} with {}:", side.name(), setting.label);
+                if !same {
+                    edge_changed = true;
+                    edges_changed.insert(i);
+                    change += &format!(" {} -> {};", describe(edge_before), describe(edge_after));
+                }
+                if before != after {
+                    partner_changed = true;
+                    partners_changed.insert(i);
+                    let lost: BTreeSet<String> = before.difference(after).cloned().collect();
+                    let gained: BTreeSet<String> = after.difference(before).cloned().collect();
+                    if !lost.is_empty() {
+                        change += &format!(" loses {}", join(&lost));
+                    }
+                    if !gained.is_empty() {
+                        change += &format!(" gains {}", join(&gained));
+                    }
+                }
+                changes.push(change.trim_end_matches(';').to_string());
+            }
+            if edge_changed {
+                edges_per_setting[s] += 1;
+            }
+            if partner_changed {
+                partners_per_setting[s] += 1;
+            }
+            if edge_changed || partner_changed {
+                settings_changed += 1;
+            }
+        }
+        if !changes.is_empty() {
+            unstable += 1;
+            let names = |sides: &BTreeSet<usize>| sides.iter().map(|i| SIDES[*i].name()).collect::<Vec<&str>>().join(", ");
+            let mut parts = Vec::new();
+            if !edges_changed.is_empty() {
+                parts.push(format!("edges change on {}", names(&edges_changed)));
+            }
+            if !partners_changed.is_empty() {
+                parts.push(format!("neighbours change on {}", names(&partners_changed)));
+            }
+            info!("{}: {} under {} of {} settings", id, parts.join(", "), settings_changed, settings.len() - 1);
+            for change in changes {
+                debug!("{}", change);
+            }
+        }
+    }
+
+    info!("Flags whose edges or neighbours differ from the current settings:");
+    info!("  edges  neighbours");
+    for ((setting, edges), partners) in settings.iter().zip(&edges_per_setting).zip(&partners_per_setting).skip(1) {
+        info!("  {:>5}  {:>10}  {}", edges, partners, setting.label);
+    }
+    info!("{} of {} flags keep the same edges and neighbours under every setting", partners[0].len() - unstable, partners[0].len());
+
+    if let Some(graph) = graph {
+        let placed: Vec<HashMap<String, Placed>> = (0..settings.len())
+            .map(|i| extracted.iter().map(|(id, _, placed)| (id.clone(), placed[i].clone())).collect())
+            .collect();
+        check_graph(graph, &placed, &settings);
+    }
+}
+
+// An adjacency in a saved arrangement holds under a setting if every flag that can fill one
+// cell still matches every flag that can fill the other, compared the way the matcher did.
+fn check_graph(saved: &SavedGraph, placed: &[HashMap<String, Placed>], settings: &[Setting]) {
+    let members = |cell: &String| -> Vec<String> {
+        let mut ids: Vec<String> = saved.multi_flags[cell].iter().filter(|id| placed[0].contains_key(*id)).cloned().collect();
+        ids.sort();
+        ids
+    };
+
+    let mut cells: Vec<(&(i32, i32), &String)> = saved.graph.graph.iter().collect();
+    cells.sort();
+    let (mut total, mut robust) = (0, 0);
+    let mut inexact = Vec::new();
+    info!("Adjacencies in {}, matched on {} with transparent {} and complex {}:", saved.path.display(), saved.matching.match_on.name(), saved.matching.transparent.name(), saved.matching.complex.name());
+    for (loc, cell) in cells {
+        for side in [Sides::RIGHT, Sides::TOP] {
+            let offset = side.offset();
+            let neighbour = match saved.graph.graph.get(&(loc.0 + offset.0, loc.1 + offset.1)) {
+                Some(neighbour) => neighbour,
+                None => continue,
+            };
+            let (a, b) = (members(cell), members(neighbour));
+            if a.is_empty() || b.is_empty() {
+                warn!("Skipping an adjacency at {:?} whose flags were not among the inputs", loc);
+                continue;
+            }
+            total += 1;
+
+            let (i, j) = (side_index(side), side_index(side.opposite()));
+            let holds = |flags: &HashMap<String, Placed>, setting: &Setting| a.iter().all(|x| b.iter().all(|y| {
+                let (x, y) = (&flags[x][i], &flags[y][j]);
+                saved.matching.edges_match(&x.0, x.1, &y.0, y.1, &setting.classes)
+            }));
+            let failing: Vec<&str> = placed.iter().zip(settings)
+                .filter(|(flags, setting)| !holds(flags, setting))
+                .map(|(_, setting)| setting.label.as_str())
+                .collect();
+            // holds under the current settings, but only through class, wildcard or complex matching
+            let differing = a.iter().flat_map(|x| b.iter().map(move |y| (x, y)))
+                .map(|(x, y)| (&placed[0][x][i].0, &placed[0][y][j].0))
+                .find(|(x, y)| x != y);
+
+            let position = match side {
+                Sides::RIGHT => "right of",
+                _ => "above",
+            };
+            let adjacency = format!("{} {} {}", b.join("/"), position, a.join("/"));
+            if failing.is_empty() {
+                robust += 1;
+            } else {
+                info!("  {}: fails with {} of {} settings", adjacency, failing.len(), settings.len());
+                for label in failing {
+                    info!("    {}", label);
+                }
+            }
+            if let Some((x, y)) = differing {
+                if holds(&placed[0], &settings[0]) {
+                    inexact.push(format!("  {}: {} against {}", adjacency, describe(x), describe(y)));
+                }
+            }
+        }
+    }
+    if !inexact.is_empty() {
+        info!("Adjacencies whose edges differ but match under the graph's rules with the current settings:");
+        for adjacency in &inexact {
+            info!("{}", adjacency);
+        }
+    }
+    info!("{} of {} adjacencies hold under every setting; {} are inexact matches", robust, total, inexact.len());
+}
@@ -0,0 +1,90 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use clap::Parser;
+
+use shared::shared::*;
+use shared::color::{delta_e2000, Lab};
+use shared::extract::SIDES;
+
+#[derive(Parser, Debug)]
+#[command(about = "Lists the flag sides whose color profiles are closest to a given flag side")]
+struct Args {
+    /// Flag id to look up
+    flag: String,
+
+    /// Side to look up; all four if omitted
+    #[arg(long)]
+    side: Option<Sides>,
+
+    /// Edge data written by edge-gen
+    #[arg(long, default_value = "flag_edges.json")]
+    edges: String,
+
+    /// Number of results listed per side
+    #[arg(short, default_value_t = 10)]
+    n: usize,
+
+    /// Compare against every side, not just the side that would touch it
+    #[arg(long)]
+    any_side: bool,
+}
+
+fn read_edge_data_from_file<P: AsRef<Path>>(path: P) -> EdgeFile {
+    let file = File::open(path).unwrap();
+    let reader = BufReader::new(file);
+
+    serde_json::from_reader(reader).unwrap()
+}
+
+fn edge_to_string(edge: &EdgeInfo) -> String {
+    edge.iter().map(|(color, prop)| format!("{} {}", color, prop)).collect::<Vec<String>>().join(", ")
+}
+
+// a transparent sample against an opaque one counts as far apart as black and white
+const TRANSPARENT_DISTANCE: f32 = 100.0;
+
+// mean CIEDE2000 difference between corresponding samples
+fn profile_distance(a: &[Option<Lab>], b: &[Option<Lab>]) -> f32 {
+    assert_eq!(a.len(), b.len(), "profiles have different sample counts");
+    a.iter().zip(b).map(|(x, y)| match (x, y) {
+        (Some(x), Some(y)) => delta_e2000(x, y),
+        (None, None) => 0.0,
+        _ => TRANSPARENT_DISTANCE,
+    }).sum::<f32>() / a.len() as f32
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let flags = read_edge_data_from_file(&args.edges).flags;
+    let query = flags.iter().find(|f| f.id == args.flag)
+        .unwrap_or_else(|| panic!("no flag {} in {}", args.flag, args.edges));
+
+    let sides: Vec<Sides> = match args.side {
+        Some(side) => vec![side],
+        None => SIDES.to_vec(),
+    };
+
+    for side in sides {
+        // a side is placed against the opposite side of its neighbour
+        let candidates: Vec<Sides> = if args.any_side { SIDES.to_vec() } else { vec![side.opposite()] };
+
+        let mut results: Vec<(f32, &FlagEdges, Sides)> = Vec::new();
+        for flag in flags.iter().filter(|f| f.id != query.id) {
+            for other in &candidates {
+                let distance = profile_distance(query.profiles.side(side), flag.profiles.side(*other));
+                results.push((distance, flag, *other));
+            }
+        }
+        results.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        println!("{} {}: {}", query.display_name(), side.name(), edge_to_string(query.edge(side)));
+        for (i, (distance, flag, other)) in results.iter().take(args.n).enumerate() {
+            let exact = if query.edge(side) == flag.edge(*other) { "  exact" } else { "" };
+            println!("{:>3}. {:6.2}  {} {}{}", i + 1, distance, flag.display_name(), other.name(), exact);
+        }
+        println!();
+    }
+}
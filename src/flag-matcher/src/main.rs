@@ -1,3 +1,5 @@
+mod stats;
+
 use std::fs::File;
 use std::path::Path;
 use std::io::prelude::*;
@@ -7,9 +9,11 @@ use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::hash::{Hash, Hasher};
 
+use clap::Parser;
 use rand::thread_rng;
 use rand::seq::SliceRandom;
 
+use shared::matching::*;
 use shared::shared::*;
 
 fn edge_to_id(edge: &EdgeInfo) -> String {
@@ -21,7 +25,19 @@ fn edge_to_id(edge: &EdgeInfo) -> String {
     output
 }
 
-fn edges_into_id(flag: &FlagInfo) -> String {
+// marks the ids of edges edge-gen flagged as complex, so they only equal each other
+const COMPLEX_PREFIX: &str = "Complex:";
+
+fn side_to_id(flag: &FlagEdges, side: Sides) -> String {
+    let id = edge_to_id(flag.edge(side));
+    if flag.complexity.side(side).complex {
+        format!("{}{}", COMPLEX_PREFIX, id)
+    } else {
+        id
+    }
+}
+
+fn edges_into_id(flag: &FlagInfo, rules: &MatchRules) -> String {
     let mut output = String::new();
     output += &flag.top;
     output += ","; 
@@ -31,16 +47,19 @@ fn edges_into_id(flag: &FlagInfo) -> String {
     output += "," ;
     output += &flag.left;
 
+    // flags only share a multiflag if their aspect ratios are interchangeable
+    if rules.aspect_tolerance.is_some() {
+        output += &format!(",@{:.2}", flag.aspect);
+    }
+
     output
 }
 
-fn read_edge_data_from_file<P: AsRef<Path>>(path: P) -> Vec<FlagEdges> {
+fn read_edge_data_from_file<P: AsRef<Path>>(path: P) -> EdgeFile {
     let file = File::open(path).unwrap();
     let reader = BufReader::new(file);
 
-    let output = serde_json::from_reader(reader).unwrap();
-
-    output
+    serde_json::from_reader(reader).unwrap()
 }
 
 // no longer used
@@ -56,11 +75,114 @@ fn read_edge_data_from_file<P: AsRef<Path>>(path: P) -> Vec<FlagEdges> {
 
 #[derive(Clone)]
 struct FlagInfo {
-    id: String,
     top: String,
     right: String,
     bottom: String,
-    left: String
+    left: String,
+    aspect: f32,
+}
+
+#[derive(Parser, Debug)]
+#[command(about = "Searches for arrangements of flags whose touching edges match")]
+struct Args {
+    /// Edge data written by edge-gen
+    #[arg(long, default_value = "flag_edges.json")]
+    edges: String,
+
+    /// Flag placed at the centre of the arrangement
+    #[arg(long, default_value = "sc")]
+    start: String,
+
+    /// Only place flags next to each other if their aspect ratios differ by at most this much
+    #[arg(long)]
+    aspect_tolerance: Option<f32>,
+
+    /// Only use flags from these regions, as named in the flag metadata
+    #[arg(long)]
+    region: Vec<String>,
+
+    /// Print statistics about the edge signatures and exit without searching
+    #[arg(long)]
+    stats: bool,
+
+    /// How transparent stretches of an edge are matched: strict (only against Transparent) or wildcard (against any color)
+    #[arg(long, default_value = "strict")]
+    transparent: TransparentMode,
+
+    /// How edges marked complex by edge-gen are matched: unmatchable, wildcard (against any edge) or same (only against the same complex edge)
+    #[arg(long, default_value = "same")]
+    complex: ComplexMode,
+
+    /// Whether edges match on the exact palette shade or on the shade's coarse class: shade or class; with class, arrangements where shades agree are tried first
+    #[arg(long, default_value = "shade")]
+    match_on: MatchOn,
+
+    /// Re-quantize every edge from its raw runs into this many units instead of edge-gen's
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    denom: Option<u32>,
+
+    /// Match on the sequence of colors along each edge, ignoring their proportions
+    #[arg(long)]
+    colors_only: bool,
+}
+
+// constraints on which multiflags may be placed next to each other
+struct MatchRules {
+    aspect_tolerance: Option<f32>,
+    aspects: HashMap<String, f32>,
+    // recorded with each saved graph so it can be checked against the same edges
+    settings: MatchSettings,
+    classes: HashMap<String, String>,
+    units: HashMap<String, Vec<String>>,
+}
+
+impl MatchRules {
+    fn edges_match(&self, a: &str, b: &str) -> bool {
+        if a.starts_with(COMPLEX_PREFIX) || b.starts_with(COMPLEX_PREFIX) {
+            return match self.settings.complex {
+                ComplexMode::Unmatchable => false,
+                ComplexMode::Wildcard => true,
+                ComplexMode::Same => a == b,
+            };
+        }
+        if a == b {
+            return true;
+        }
+        if !self.settings.inexact() {
+            return false;
+        }
+        let (a, b) = (&self.units[a], &self.units[b]);
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| self.settings.colors_match(x, y, &self.classes))
+    }
+
+    // lists each multiflag under every edge that it could be placed against, not just its own
+    fn widen_edge_map<'a>(&self, edges: HashMap<String, Vec<String>>, lookups: impl Iterator<Item = &'a str>) -> HashMap<String, Vec<String>> {
+        if !self.settings.inexact() && self.settings.complex != ComplexMode::Wildcard {
+            return edges;
+        }
+        let mut widened = HashMap::new();
+        for lookup in lookups {
+            if widened.contains_key(lookup) {
+                continue;
+            }
+            let mut ids: Vec<String> = edges.iter()
+                .filter(|(edge, _)| self.edges_match(lookup, edge))
+                .flat_map(|(_, ids)| ids.iter().cloned())
+                .collect();
+            ids.sort();
+            if !ids.is_empty() {
+                widened.insert(lookup.to_string(), ids);
+            }
+        }
+        widened
+    }
+
+    fn neighbours_compatible(&self, a: &MultiFlag, b: &MultiFlag) -> bool {
+        match self.aspect_tolerance {
+            Some(tolerance) => (self.aspects[&a.id] - self.aspects[&b.id]).abs() <= tolerance,
+            None => true,
+        }
+    }
 }
 
 struct EdgeData {
@@ -106,7 +228,7 @@ fn dist_sq(l1: (i32, i32)) -> i32 {
     l1.0.pow(2) + l1.1.pow(2)
 }
 
-fn add_next_states(state: &FlagGraph, next_states: &mut VecDeque<FlagGraph>, edge_data: &EdgeData, multi_flags: &HashMap<String, MultiFlag>) -> i32 {
+fn add_next_states(state: &FlagGraph, next_states: &mut VecDeque<FlagGraph>, edge_data: &EdgeData, multi_flags: &HashMap<String, MultiFlag>, rules: &MatchRules) -> i32 {
     let mut states_added = 0;
 
     let gen_new_state = |location: (i32, i32), add_id: String| {
@@ -121,7 +243,7 @@ fn add_next_states(state: &FlagGraph, next_states: &mut VecDeque<FlagGraph>, edg
         next_state
     };
 
-    let side_matches = |loc: (i32, i32), side: Sides, id: &String| {
+    let side_matches = |loc: (i32, i32), side: Sides, flag: &MultiFlag| {
         use Sides::*;
         let check_loc = match side {
             TOP => (loc.0, loc.1 + 1),
@@ -130,20 +252,24 @@ fn add_next_states(state: &FlagGraph, next_states: &mut VecDeque<FlagGraph>, edg
             LEFT => (loc.0 - 1, loc.1),
         };
 
-        let check_value = state.graph.get(&check_loc);
-        if check_value.is_none() {
-            return true;
-        } else {
-            let check_value = check_value.unwrap();
-            match side.opposite() {
-                TOP => &multi_flags.get(check_value).unwrap().top == id,
-                RIGHT => &multi_flags.get(check_value).unwrap().right == id,
-                BOTTOM => &multi_flags.get(check_value).unwrap().bottom == id,
-                LEFT => &multi_flags.get(check_value).unwrap().left == id
+        match state.graph.get(&check_loc) {
+            None => true,
+            Some(check_value) => {
+                let neighbour = multi_flags.get(check_value).unwrap();
+                rules.edges_match(neighbour.side(side.opposite()), flag.side(side)) && rules.neighbours_compatible(neighbour, flag)
             }
         }
     };
 
+    // number of placed neighbours whose touching edge has exactly the same shades
+    let shade_agreements = |loc: (i32, i32), flag: &MultiFlag| {
+        [Sides::TOP, Sides::RIGHT, Sides::BOTTOM, Sides::LEFT].iter().filter(|side| {
+            let offset = side.offset();
+            state.graph.get(&(loc.0 + offset.0, loc.1 + offset.1))
+                .is_some_and(|neighbour| multi_flags[neighbour].side(side.opposite()) == flag.side(**side))
+        }).count()
+    };
+
     // locate all boundary cells
     let mut boundary_cells = HashMap::new();
     
@@ -175,13 +301,18 @@ fn add_next_states(state: &FlagGraph, next_states: &mut VecDeque<FlagGraph>, edg
         if edge_data.contains_key(id) {
             let mut cur_edge_data = edge_data.get(id).unwrap().clone();
             cur_edge_data.shuffle(&mut thread_rng());
+            // states are explored last in first out, so flags that agree on shade with more of
+            // their neighbours go last
+            if rules.settings.match_on == MatchOn::Class {
+                cur_edge_data.sort_by_key(|flag_id| shade_agreements(loc, &multi_flags[flag_id]));
+            }
             for flag_id in &cur_edge_data {
                 if state.remaining_flags.contains_key(flag_id) {
                     let flag = multi_flags.get(flag_id).unwrap();
-                    if side_matches(loc, Sides::TOP, &flag.top) &&
-                        side_matches(loc, Sides::RIGHT, &flag.right) &&
-                        side_matches(loc, Sides::BOTTOM, &flag.bottom) &&
-                        side_matches(loc, Sides::LEFT, &flag.left)
+                    if side_matches(loc, Sides::TOP, flag) &&
+                        side_matches(loc, Sides::RIGHT, flag) &&
+                        side_matches(loc, Sides::BOTTOM, flag) &&
+                        side_matches(loc, Sides::LEFT, flag)
                     {
                         next_states.push_back(gen_new_state(loc, String::from(flag_id)));
                         states_added += 1;
@@ -204,15 +335,33 @@ fn add_next_states(state: &FlagGraph, next_states: &mut VecDeque<FlagGraph>, edg
 
         place_flag_at_loc(
             &multi_flags.get(state.graph.get(&from_offset).unwrap()).unwrap().side(boundary.from.opposite()).to_string(),
-            &edge_data.side(boundary.from), next_states, boundary.loc
+            edge_data.side(boundary.from), next_states, boundary.loc
         );
     }
-    return states_added;
+    states_added
 }
 
-fn save_graph_to_file(flag_graph: &FlagGraph) {
+// seams whose edges only match by class or wildcard, rather than shade for shade
+fn count_inexact_seams(flag_graph: &FlagGraph, multi_flags: &HashMap<String, MultiFlag>) -> usize {
+    let mut seams = 0;
+    for (loc, id) in &flag_graph.graph {
+        for side in [Sides::TOP, Sides::RIGHT] {
+            let offset = side.offset();
+            if let Some(neighbour) = flag_graph.graph.get(&(loc.0 + offset.0, loc.1 + offset.1)) {
+                if multi_flags[id].side(side) != multi_flags[neighbour].side(side.opposite()) {
+                    seams += 1;
+                }
+            }
+        }
+    }
+    seams
+}
+
+fn save_graph_to_file(flag_graph: &FlagGraph, rules: &MatchRules) {
+    let mut serialized = FlagGraphSerialize::new(flag_graph);
+    serialized.matching = rules.settings.clone();
     let mut output_file = File::create("best_graph_found_".to_string() + &flag_graph.idx.to_string() + ".json").unwrap();
-    output_file.write_all(serde_json::to_string(&FlagGraphSerialize::new(&flag_graph)).unwrap().as_bytes()).unwrap();
+    output_file.write_all(serde_json::to_string(&serialized).unwrap().as_bytes()).unwrap();
 }
 
 fn save_multi_flags_to_file(multi_flags: &HashMap<String, HashSet<String>>) {
@@ -220,7 +369,7 @@ fn save_multi_flags_to_file(multi_flags: &HashMap<String, HashSet<String>>) {
     output_file.write_all(serde_json::to_string(multi_flags).unwrap().as_bytes()).unwrap();
 }
 
-fn generate_flag_arrangement(multi_flags_count: &HashMap<String, u32>, flags_to_multi_flags: &HashMap<String, String>, edge_data: &EdgeData, multi_flags: &HashMap<String, MultiFlag>, start_id: String) -> Option<FlagGraph> {
+fn generate_flag_arrangement(multi_flags_count: &HashMap<String, u32>, flags_to_multi_flags: &HashMap<String, String>, edge_data: &EdgeData, multi_flags: &HashMap<String, MultiFlag>, rules: &MatchRules, start_id: String) -> Option<FlagGraph> {
     let mut initial_state = FlagGraph {
         graph: HashMap::new(),
         remaining_flags: multi_flags_count.clone(),
@@ -241,28 +390,34 @@ fn generate_flag_arrangement(multi_flags_count: &HashMap<String, u32>, flags_to_
 
     let mut next_states = VecDeque::new();
 
-    add_next_states(&initial_state, &mut next_states, &edge_data, &multi_flags);
+    add_next_states(&initial_state, &mut next_states, edge_data, multi_flags, rules);
 
     let mut best_result: Option<FlagGraph> = None;
     let mut i = 0;
     while !next_states.is_empty() {
         let s = next_states.pop_back().unwrap();
-        let new_states = add_next_states(&s, &mut next_states, &edge_data, &multi_flags);
+        let new_states = add_next_states(&s, &mut next_states, edge_data, multi_flags, rules);
 
         if new_states == 0 {
             if best_result.is_none() {
                 best_result = Some(s.clone());
-                save_graph_to_file(&s);
+                save_graph_to_file(&s, rules);
                 println!("New best found: ");
                 println!("{:?} ({} flags)", s.graph, s.idx);
+                if rules.settings.match_on == MatchOn::Class {
+                    println!("{} seams where shades differ", count_inexact_seams(&s, multi_flags));
+                }
                 i -= 1000;
             } else {
                 let prev_best = best_result.unwrap();
                 if prev_best.idx < s.idx {
                     best_result = Some(s.clone());
-                    save_graph_to_file(&s);
+                    save_graph_to_file(&s, rules);
                     println!("New best found: ");
                     println!("{:?} ({} flags)", s.graph, s.idx);
+                    if rules.settings.match_on == MatchOn::Class {
+                        println!("{} seams where shades differ", count_inexact_seams(&s, multi_flags));
+                    }
                     i -= 1000;
                 } else {
                     best_result = Some(prev_best);
@@ -292,7 +447,7 @@ fn generate_flag_arrangement(multi_flags_count: &HashMap<String, u32>, flags_to_
             }
             initial_state.graph.insert((0, 0), initial_flag);
 
-            add_next_states(&initial_state, &mut next_states, &edge_data, &multi_flags);
+            add_next_states(&initial_state, &mut next_states, edge_data, multi_flags, rules);
             i = 0;
         }
 
@@ -301,18 +456,59 @@ fn generate_flag_arrangement(multi_flags_count: &HashMap<String, u32>, flags_to_
 }
 
 fn main() {
-    let flag_data = read_edge_data_from_file("./flag_edges.json");
+    let args = Args::parse();
+
+    let edge_file = read_edge_data_from_file(&args.edges);
+    println!("Edges generated with palette {} ({} metric)", edge_file.source.palette, edge_file.source.metric);
+    let mut flag_data = edge_file.flags;
+    if !args.region.is_empty() {
+        let in_region = |f: &FlagEdges, region: &str| f.metadata.as_ref().is_some_and(|m| region.eq_ignore_ascii_case(&m.region));
+        let unknown: Vec<&str> = args.region.iter().filter(|r| !flag_data.iter().any(|f| in_region(f, r))).map(|r| r.as_str()).collect();
+        if !unknown.is_empty() {
+            let mut known: Vec<&str> = flag_data.iter().filter_map(|f| Some(f.metadata.as_ref()?.region.as_str())).collect();
+            known.sort();
+            known.dedup();
+            eprintln!("No flags in region {}; the regions in {} are {}", unknown.join(", "), args.edges, known.join(", "));
+            std::process::exit(1);
+        }
+        flag_data.retain(|f| f.metadata.as_ref().is_some_and(|m| args.region.iter().any(|r| r.eq_ignore_ascii_case(&m.region))));
+        println!("{} flags in {}", flag_data.len(), args.region.join(", "));
+    }
+    let settings = MatchSettings {
+        transparent: args.transparent,
+        complex: args.complex,
+        match_on: args.match_on,
+        denom: args.denom,
+        colors_only: args.colors_only,
+    };
+    if args.denom.is_some() || args.colors_only {
+        for f in &mut flag_data {
+            settings.requantize(f, edge_file.source.min_run);
+        }
+        let denom = args.denom.unwrap_or(edge_file.source.denom);
+        if args.colors_only {
+            println!("Matching color sequences only, after quantizing to {} units", denom);
+        } else {
+            println!("Edges re-quantized to {} units", denom);
+        }
+    }
 
     // process flags into id strings
 
+    let mut names = HashMap::new();
     let mut flags = HashMap::new();
-    for f in flag_data {
+    let mut units = HashMap::new();
+    for f in &flag_data {
+        names.insert(f.id.clone(), f.display_name());
+        for side in [Sides::TOP, Sides::RIGHT, Sides::BOTTOM, Sides::LEFT] {
+            units.insert(side_to_id(f, side), edge_units(f.edge(side)));
+        }
         flags.insert(String::from(&f.id), FlagInfo {
-            id: String::from(&f.id),
-            top: edge_to_id(&f.top),
-            right: edge_to_id(&f.right),
-            bottom: edge_to_id(&f.bottom),
-            left: edge_to_id(&f.left),
+            top: side_to_id(f, Sides::TOP),
+            right: side_to_id(f, Sides::RIGHT),
+            bottom: side_to_id(f, Sides::BOTTOM),
+            left: side_to_id(f, Sides::LEFT),
+            aspect: f.aspect,
         });
     }
 
@@ -320,9 +516,16 @@ fn main() {
     let mut multi_flags_count: HashMap<String, u32> = HashMap::new();
     let mut multi_flags = HashMap::new();
     let mut flags_to_multi_flags = HashMap::new();
+    let mut rules = MatchRules {
+        aspect_tolerance: args.aspect_tolerance,
+        aspects: HashMap::new(),
+        settings,
+        classes: edge_file.source.classes.clone(),
+        units,
+    };
 
     for f in &flags {
-        let edge_id = edges_into_id(f.1);
+        let edge_id = edges_into_id(f.1, &rules);
         flags_to_multi_flags.insert(f.0.clone(), edge_id.clone());
         if multi_flags_to_flags.contains_key(&edge_id) {
             multi_flags_to_flags.get_mut(&edge_id).unwrap().insert(String::from(f.0));
@@ -332,6 +535,7 @@ fn main() {
             new_set.insert(String::from(f.0));
             multi_flags_to_flags.insert(String::from(&edge_id), new_set);
             multi_flags_count.insert(String::from(&edge_id), 1);
+            rules.aspects.insert(String::from(&edge_id), f.1.aspect);
             multi_flags.insert(String::from(&edge_id), MultiFlag {
                 id: edge_id,
                 top: f.1.top.clone(),
@@ -343,7 +547,21 @@ fn main() {
     }
     println!("{} flags -> {} multiflags", flags.len(), multi_flags.len());
     println!("{:?}", multi_flags_to_flags);
-    println!("");
+    println!();
+    println!("Flags sharing all four edges:");
+    let mut shared_groups: Vec<Vec<&String>> = multi_flags_to_flags.values()
+        .filter(|ids| ids.len() > 1)
+        .map(|ids| {
+            let mut group: Vec<&String> = ids.iter().map(|id| &names[id]).collect();
+            group.sort();
+            group
+        })
+        .collect();
+    shared_groups.sort();
+    for group in shared_groups {
+        println!("  {}", group.iter().map(|n| n.as_str()).collect::<Vec<&str>>().join(", "));
+    }
+    println!();
     
     let mut top_edges = HashMap::new();
     let mut right_edges = HashMap::new();
@@ -359,19 +577,25 @@ fn main() {
     };
 
     for mf in &multi_flags {
-        add_edge_to_map(&mut top_edges, &mf.1.top, &mf.0);
-        add_edge_to_map(&mut right_edges, &mf.1.right, &mf.0);
-        add_edge_to_map(&mut bottom_edges, &mf.1.bottom, &mf.0);
-        add_edge_to_map(&mut left_edges, &mf.1.left, &mf.0);
+        add_edge_to_map(&mut top_edges, &mf.1.top, mf.0);
+        add_edge_to_map(&mut right_edges, &mf.1.right, mf.0);
+        add_edge_to_map(&mut bottom_edges, &mf.1.bottom, mf.0);
+        add_edge_to_map(&mut left_edges, &mf.1.left, mf.0);
     }
 
+    // a flag's top edge is looked up by the bottom edge of the flag above it, and so on
     let edge_data = EdgeData {
-        top: top_edges,
-        right: right_edges,
-        bottom: bottom_edges,
-        left: left_edges,
+        top: rules.widen_edge_map(top_edges, multi_flags.values().map(|mf| mf.bottom.as_str())),
+        right: rules.widen_edge_map(right_edges, multi_flags.values().map(|mf| mf.left.as_str())),
+        bottom: rules.widen_edge_map(bottom_edges, multi_flags.values().map(|mf| mf.top.as_str())),
+        left: rules.widen_edge_map(left_edges, multi_flags.values().map(|mf| mf.right.as_str())),
     };
 
+    if args.stats {
+        stats::print_report(&flag_data, &names, &flags_to_multi_flags, &multi_flags_count, &multi_flags, &edge_data, &rules);
+        return;
+    }
+
     save_multi_flags_to_file(&multi_flags_to_flags);
-    generate_flag_arrangement(&multi_flags_count, &flags_to_multi_flags, &edge_data, &multi_flags, "sc".to_string());
+    generate_flag_arrangement(&multi_flags_count, &flags_to_multi_flags, &edge_data, &multi_flags, &rules, args.start);
 }
\ No newline at end of file
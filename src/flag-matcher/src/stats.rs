@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use shared::shared::*;
+
+use crate::{EdgeData, MatchRules};
+
+const SIDES: [Sides; 4] = [Sides::TOP, Sides::RIGHT, Sides::BOTTOM, Sides::LEFT];
+
+// whether any other flag could be placed against this side under the current rules
+fn side_matchable(flag: &MultiFlag, side: Sides, edge_data: &EdgeData, multi_flags: &HashMap<String, MultiFlag>, multi_flags_count: &HashMap<String, u32>, rules: &MatchRules) -> bool {
+    let candidates = match edge_data.side(side.opposite()).get(flag.side(side)) {
+        Some(candidates) => candidates,
+        None => return false,
+    };
+    candidates.iter().any(|id| {
+        let other = &multi_flags[id];
+        (other.id != flag.id || multi_flags_count[id] > 1)
+            && rules.edges_match(flag.side(side), other.side(side.opposite()))
+            && rules.neighbours_compatible(flag, other)
+    })
+}
+
+fn print_signatures(flags: &[FlagEdges], flags_to_multi_flags: &HashMap<String, String>, multi_flags: &HashMap<String, MultiFlag>) {
+    println!("Edge signatures:");
+    for side in SIDES {
+        let mut counts: HashMap<&str, u32> = HashMap::new();
+        for f in flags {
+            *counts.entry(multi_flags[&flags_to_multi_flags[&f.id]].side(side)).or_insert(0) += 1;
+        }
+        let mut counts: Vec<(&str, u32)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let single = counts.iter().filter(|c| c.1 == 1).count();
+        println!("  {}: {} distinct across {} flags, {} used by a single flag", side.name(), counts.len(), flags.len(), single);
+        for (signature, count) in counts.iter().filter(|c| c.1 > 1) {
+            println!("    {:>4}  {}", count, signature);
+        }
+    }
+    println!();
+}
+
+fn print_unmatchable(flags: &[FlagEdges], names: &HashMap<String, String>, flags_to_multi_flags: &HashMap<String, String>, multi_flags_count: &HashMap<String, u32>, multi_flags: &HashMap<String, MultiFlag>, edge_data: &EdgeData, rules: &MatchRules) {
+    println!("Sides no other flag can be placed against:");
+    let mut isolated = Vec::new();
+    for f in flags {
+        let multi_flag = &multi_flags[&flags_to_multi_flags[&f.id]];
+        let unmatched: Vec<&str> = SIDES.iter()
+            .filter(|side| !side_matchable(multi_flag, **side, edge_data, multi_flags, multi_flags_count, rules))
+            .map(|side| side.name())
+            .collect();
+        if unmatched.len() == SIDES.len() {
+            isolated.push(names[&f.id].as_str());
+        } else if !unmatched.is_empty() {
+            println!("  {}: {}", names[&f.id], unmatched.join(", "));
+        }
+    }
+    println!();
+    println!("{} flags match nothing on any side and can only be placed as the start flag:", isolated.len());
+    for name in isolated {
+        println!("  {}", name);
+    }
+    println!();
+}
+
+fn print_colors(flags: &[FlagEdges]) {
+    let mut units: HashMap<&str, u32> = HashMap::new();
+    let mut flag_counts: HashMap<&str, u32> = HashMap::new();
+    for f in flags {
+        let mut seen = Vec::new();
+        for side in SIDES {
+            for (color, prop) in f.edge(side) {
+                *units.entry(color).or_insert(0) += prop;
+                if !seen.contains(&color.as_str()) {
+                    seen.push(color);
+                }
+            }
+        }
+        for color in seen {
+            *flag_counts.entry(color).or_insert(0) += 1;
+        }
+    }
+
+    let total: u32 = units.values().sum();
+    let mut units: Vec<(&str, u32)> = units.into_iter().collect();
+    units.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    println!("Edge colors:");
+    for (color, count) in units {
+        println!("  {:<12} {:>6} units {:5.1}%  on {} flags", color, count, 100.0 * count as f32 / total as f32, flag_counts[color]);
+    }
+}
+
+pub fn print_report(flags: &[FlagEdges], names: &HashMap<String, String>, flags_to_multi_flags: &HashMap<String, String>, multi_flags_count: &HashMap<String, u32>, multi_flags: &HashMap<String, MultiFlag>, edge_data: &EdgeData, rules: &MatchRules) {
+    print_signatures(flags, flags_to_multi_flags, multi_flags);
+    print_unmatchable(flags, names, flags_to_multi_flags, multi_flags_count, multi_flags, edge_data, rules);
+    print_colors(flags);
+}
@@ -1,11 +1,13 @@
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::io::prelude::*;
 use std::io::BufReader;
 use regex::Regex;
 
 use shared::shared::*;
+use shared::raster::{is_svg, rasterize_svg};
 use image::{RgbaImage, DynamicImage};
 
 const FLAG_DIMS: (u32, u32) = (320, 233);
@@ -14,22 +16,75 @@ fn read_multi_flags_from_file<P: AsRef<Path>>(path: P) -> HashMap<String, HashSe
     let file = File::open(path).unwrap();
     let reader = BufReader::new(file);
 
-    let output = serde_json::from_reader(reader).unwrap();
-
-    output
+    serde_json::from_reader(reader).unwrap()
 }
 
-fn read_graph_from_file<P: AsRef<Path>>(path: P) -> FlagGraph {
+fn read_graph_from_file<P: AsRef<Path>>(path: P) -> FlagGraphSerialize {
     let file = File::open(path).unwrap();
     let reader = BufReader::new(file);
 
-    let serialized: FlagGraphSerialize = serde_json::from_reader(reader).unwrap();
+    serde_json::from_reader(reader).unwrap()
+}
+
+// the edge file is optional; without it the legend only lists ids and seams aren't checked.
+// edges are re-quantized the way the matcher did when it saved the graph
+fn read_edges<P: AsRef<Path>>(path: P, graph: &FlagGraphSerialize) -> HashMap<String, FlagEdges> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return HashMap::new(),
+    };
+    let edge_file: EdgeFile = serde_json::from_reader(BufReader::new(file)).unwrap();
+    let min_run = edge_file.source.min_run;
+    edge_file.flags.into_iter().map(|mut f| {
+        graph.matching.requantize(&mut f, min_run);
+        (f.id.clone(), f)
+    }).collect()
+}
+
+fn edge_to_string(edge: &EdgeInfo) -> String {
+    edge.iter().map(|(color, prop)| format!("{}{}", color, prop)).collect::<Vec<String>>().join(" ")
+}
+
+// neighbouring flags whose touching edges are not identical, e.g. when matched by color class
+fn differing_seams(placed: &HashMap<(i32, i32), String>, edges: &HashMap<String, FlagEdges>) -> Vec<String> {
+    let mut locs: Vec<&(i32, i32)> = placed.keys().collect();
+    locs.sort();
+    let mut seams = Vec::new();
+    for loc in locs {
+        for side in [Sides::TOP, Sides::RIGHT] {
+            let offset = side.offset();
+            let neighbour = match placed.get(&(loc.0 + offset.0, loc.1 + offset.1)) {
+                Some(neighbour) => neighbour,
+                None => continue,
+            };
+            let (a, b) = match (edges.get(&placed[loc]), edges.get(neighbour)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue,
+            };
+            if a.edge(side) != b.edge(side.opposite()) {
+                seams.push(format!("{} {} / {} {}: {} against {}", a.id, side.name(), b.id, side.opposite().name(), edge_to_string(a.edge(side)), edge_to_string(b.edge(side.opposite()))));
+            }
+        }
+    }
+    seams
+}
 
-    serialized.to_flag_graph()
+// prefers the vector source of a flag when one exists
+fn flag_path(id: &str) -> PathBuf {
+    let svg = PathBuf::from(format!("./flags/{}.svg", id));
+    if svg.exists() {
+        svg
+    } else {
+        PathBuf::from(format!("./flags/{}.png", id))
+    }
 }
 
 fn load_flag<P: AsRef<Path>>(path: P) -> DynamicImage {
-    image::open(path).unwrap().resize_exact(FLAG_DIMS.0, FLAG_DIMS.1, image::imageops::Gaussian)
+    if is_svg(&path) {
+        rasterize_svg(path, FLAG_DIMS.0, Some(FLAG_DIMS.1))
+    } else {
+        image::open(path).unwrap().resize_exact(FLAG_DIMS.0, FLAG_DIMS.1, image::imageops::Gaussian)
+    }
 }
 
 fn main() {
@@ -45,7 +100,7 @@ fn main() {
             let groups: Vec<regex::Captures> = search_regex.captures_iter(file_name).collect();
             let group = &groups[0];
 
-            let flags = (&group[1]).parse::<i32>().unwrap();
+            let flags = group[1].parse::<i32>().unwrap();
             if flags > max {
                 max = flags;
             }
@@ -54,8 +109,10 @@ fn main() {
 
     let file_open = "./best_graph_found_".to_string() + &max.to_string() + ".json";
     println!("Opening file: {}", file_open);
-    let arrangement = read_graph_from_file(file_open);
+    let serialized = read_graph_from_file(file_open);
+    let arrangement = serialized.to_flag_graph();
     let multi_flag_map = read_multi_flags_from_file("./multi_flags.json");
+    let edges = read_edges("./flag_edges.json", &serialized);
 
     let mut multi_flags: HashMap<String, Vec<String>> = HashMap::new();
     for (multiflag, flags) in multi_flag_map {
@@ -69,7 +126,7 @@ fn main() {
     let mut max_x = 0;
     let mut min_y = 0;
     let mut max_y = 0;
-    for (loc, _) in &graph {
+    for loc in graph.keys() {
         if loc.0 < min_x {
             min_x = loc.0;
         }
@@ -83,36 +140,58 @@ fn main() {
             max_y = loc.1;
         }
     }
-    let x_dim = (1 + max_x - min_x).abs() as u32;
-    let y_dim = (1 + max_y - min_y).abs() as u32;
+    let x_dim = (1 + max_x - min_x).unsigned_abs();
+    let y_dim = (1 + max_y - min_y).unsigned_abs();
 
     let width = x_dim * FLAG_DIMS.0;
     let height = y_dim * FLAG_DIMS.1;
     
     println!("Creating image of {}x{} flags", x_dim, y_dim);
     let mut output_image = RgbaImage::new(width, height);
+    let mut legend = Vec::new();
+    let mut placed = HashMap::new();
 
-    let mut i = 1;
-
-    for (loc, flag_id) in &graph {
+    for (i, (loc, flag_id)) in (1..).zip(graph.iter()) {
         let x_graph_coord = loc.0 - min_x;
         let y_graph_coord = max_y - loc.1;
 
-        let x_coord = x_graph_coord.abs() as u32 * FLAG_DIMS.0;
-        let y_coord = y_graph_coord.abs() as u32 * FLAG_DIMS.1;
+        let x_coord = x_graph_coord.unsigned_abs() * FLAG_DIMS.0;
+        let y_coord = y_graph_coord.unsigned_abs() * FLAG_DIMS.1;
 
         let place_flag = multi_flags.get_mut(flag_id).unwrap().pop().unwrap();
-        println!("({}/{}) Placing {} at {} {}", i, flag_count, place_flag, x_graph_coord, y_graph_coord);
-
-        let flag_img = load_flag("./flags/".to_string() + &place_flag + ".png");
+        let label = match edges.get(&place_flag).and_then(|f| f.metadata.as_ref()) {
+            Some(m) => format!("{} {} ({})", place_flag, m.name, m.region),
+            None => place_flag.clone(),
+        };
+        println!("({}/{}) Placing {} at {} {}", i, flag_count, label, x_graph_coord, y_graph_coord);
+        legend.push((y_graph_coord, x_graph_coord, label));
+        placed.insert(*loc, place_flag.clone());
+
+        let flag_img = load_flag(flag_path(&place_flag));
         let flag_view = flag_img.to_rgba8();
 
         image::imageops::overlay(&mut output_image, &flag_view, x_coord, y_coord);
-        i += 1;
     }
 
     println!("Saving image...");
     output_image.save("output_image_".to_string() + &flag_count.to_string() + ".png").unwrap();
+
+    // row and column of every flag in the image, read left to right, top to bottom
+    legend.sort();
+    let mut legend_file = File::create("output_image_".to_string() + &flag_count.to_string() + ".txt").unwrap();
+    for (row, col, label) in legend {
+        writeln!(legend_file, "{:>3} {:>3}  {}", row, col, label).unwrap();
+    }
+
+    let seams = differing_seams(&placed, &edges);
+    if !seams.is_empty() {
+        println!("{} seams where the touching edges differ:", seams.len());
+        writeln!(legend_file, "\nSeams where the touching edges differ:").unwrap();
+        for seam in seams {
+            println!("  {}", seam);
+            writeln!(legend_file, "  {}", seam).unwrap();
+        }
+    }
     println!("Done!");
 
 }
@@ -0,0 +1,153 @@
+use image::Rgb;
+
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+pub type Lab = [f32; 3];
+
+// D65 reference white
+const WHITE_X: f32 = 0.950_47;
+const WHITE_Y: f32 = 1.0;
+const WHITE_Z: f32 = 1.088_83;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorMetric {
+    Rgb,
+    Cie76,
+    Ciede2000,
+}
+
+impl ColorMetric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorMetric::Rgb => "rgb",
+            ColorMetric::Cie76 => "cie76",
+            ColorMetric::Ciede2000 => "ciede2000",
+        }
+    }
+}
+
+impl FromStr for ColorMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rgb" => Ok(ColorMetric::Rgb),
+            "cie76" | "lab" => Ok(ColorMetric::Cie76),
+            "ciede2000" | "de2000" => Ok(ColorMetric::Ciede2000),
+            _ => Err(format!("unknown color metric: {}", s)),
+        }
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn lab_f(t: f32) -> f32 {
+    let delta: f32 = 6.0 / 29.0;
+    if t > delta.powi(3) {
+        t.cbrt()
+    } else {
+        t / (3.0 * delta * delta) + 4.0 / 29.0
+    }
+}
+
+pub fn rgb_to_lab(color: &Rgb<u8>) -> Lab {
+    let r = srgb_to_linear(color[0]);
+    let g = srgb_to_linear(color[1]);
+    let b = srgb_to_linear(color[2]);
+
+    let x = 0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = 0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b;
+
+    let fx = lab_f(x / WHITE_X);
+    let fy = lab_f(y / WHITE_Y);
+    let fz = lab_f(z / WHITE_Z);
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+pub fn delta_e76(c1: &Lab, c2: &Lab) -> f32 {
+    ((c1[0] - c2[0]).powi(2) + (c1[1] - c2[1]).powi(2) + (c1[2] - c2[2]).powi(2)).sqrt()
+}
+
+// hue angle in degrees, in [0, 360)
+fn hue_angle(b: f32, a: f32) -> f32 {
+    if a == 0.0 && b == 0.0 {
+        return 0.0;
+    }
+    let h = b.atan2(a).to_degrees();
+    if h < 0.0 {
+        h + 360.0
+    } else {
+        h
+    }
+}
+
+// CIEDE2000 colour difference with unit weighting factors (kL = kC = kH = 1)
+pub fn delta_e2000(c1: &Lab, c2: &Lab) -> f32 {
+    let (l1, a1, b1) = (c1[0], c1[1], c1[2]);
+    let (l2, a2, b2) = (c2[0], c2[1], c2[2]);
+
+    let c_bar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+    let c_bar7 = c_bar.powi(7);
+    let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + 25f32.powi(7))).sqrt());
+
+    let a1p = (1.0 + g) * a1;
+    let a2p = (1.0 + g) * a2;
+    let c1p = (a1p * a1p + b1 * b1).sqrt();
+    let c2p = (a2p * a2p + b2 * b2).sqrt();
+    let h1p = hue_angle(b1, a1p);
+    let h2p = hue_angle(b2, a2p);
+
+    let dlp = l2 - l1;
+    let dcp = c2p - c1p;
+    let dhp = if c1p * c2p == 0.0 {
+        0.0
+    } else if (h2p - h1p).abs() <= 180.0 {
+        h2p - h1p
+    } else if h2p - h1p > 180.0 {
+        h2p - h1p - 360.0
+    } else {
+        h2p - h1p + 360.0
+    };
+    let dhp_big = 2.0 * (c1p * c2p).sqrt() * (dhp.to_radians() / 2.0).sin();
+
+    let lp_bar = (l1 + l2) / 2.0;
+    let cp_bar = (c1p + c2p) / 2.0;
+    let hp_bar = if c1p * c2p == 0.0 {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= 180.0 {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 360.0 {
+        (h1p + h2p + 360.0) / 2.0
+    } else {
+        (h1p + h2p - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (hp_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * hp_bar).to_radians().cos()
+        + 0.32 * (3.0 * hp_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * hp_bar - 63.0).to_radians().cos();
+    let d_theta = 30.0 * (-((hp_bar - 275.0) / 25.0).powi(2)).exp();
+    let cp_bar7 = cp_bar.powi(7);
+    let r_c = 2.0 * (cp_bar7 / (cp_bar7 + 25f32.powi(7))).sqrt();
+    let l_term = (lp_bar - 50.0).powi(2);
+    let s_l = 1.0 + 0.015 * l_term / (20.0 + l_term).sqrt();
+    let s_c = 1.0 + 0.045 * cp_bar;
+    let s_h = 1.0 + 0.015 * cp_bar * t;
+    let r_t = -(2.0 * d_theta * PI / 180.0).sin() * r_c;
+
+    let l_part = dlp / s_l;
+    let c_part = dcp / s_c;
+    let h_part = dhp_big / s_h;
+
+    (l_part * l_part + c_part * c_part + h_part * h_part + r_t * c_part * h_part).sqrt()
+}
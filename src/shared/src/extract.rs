@@ -0,0 +1,472 @@
+use image::{DynamicImage, Rgb, Rgba, RgbaImage};
+
+use crate::color::{rgb_to_lab, ColorMetric, Lab};
+use crate::frame::trim_frame;
+use crate::palette::Palette;
+use crate::runs::*;
+use crate::shared::*;
+
+pub const SIDES: [Sides; 4] = [Sides::TOP, Sides::RIGHT, Sides::BOTTOM, Sides::LEFT];
+
+// settings that decide how an image's edges are classified and quantized
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    pub palette: Palette,
+    pub metric: ColorMetric,
+    pub denom: u32,
+    pub band: u32,
+    pub min_run: f32,
+    pub alpha_threshold: u8,
+    // edges scoring above this are marked complex
+    pub complex_threshold: Option<f32>,
+    pub profile_samples: u32,
+    // widest frame or padding, in pixels, trimmed before sampling; 0 disables trimming
+    pub max_frame: u32,
+    // measure run boundaries to a fraction of a pixel rather than whole pixels
+    pub subpixel: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            palette: Palette::builtin(),
+            metric: ColorMetric::Ciede2000,
+            denom: 24,
+            band: 1,
+            min_run: 0.5,
+            alpha_threshold: 128,
+            complex_threshold: None,
+            profile_samples: 64,
+            max_frame: 4,
+            subpixel: true,
+        }
+    }
+}
+
+// yields, for each position along a side, the band of pixels from the edge inwards
+pub struct SideIterator<'a> {
+    side: Sides,
+    idx: u32,
+    depth: u32,
+    img: &'a RgbaImage,
+}
+
+impl SideIterator<'_> {
+    pub fn new(side: Sides, img: &RgbaImage, depth: u32) -> SideIterator<'_> {
+        let max_depth = match side {
+            Sides::TOP | Sides::BOTTOM => img.height(),
+            Sides::LEFT | Sides::RIGHT => img.width(),
+        };
+        SideIterator {
+            side,
+            idx: 0,
+            depth: depth.clamp(1, max_depth),
+            img,
+        }
+    }
+
+    pub fn length(&self) -> u32 {
+        match self.side {
+            Sides::TOP | Sides::BOTTOM => self.img.width(),
+            Sides::LEFT | Sides::RIGHT => self.img.height(),
+        }
+    }
+}
+
+impl Iterator for SideIterator<'_> {
+    type Item = Vec<Rgba<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.idx;
+        self.idx += 1;
+
+        if idx >= self.length() {
+            return None;
+        }
+
+        let (width, height) = self.img.dimensions();
+        Some((0..self.depth).map(|d| {
+            let (x, y) = side_pixel_coords(self.side, idx, d, width, height);
+            *self.img.get_pixel(x, y)
+        }).collect())
+    }
+}
+
+// position `idx` along a side, `depth` pixels in from the edge
+pub fn side_pixel_coords(side: Sides, idx: u32, depth: u32, width: u32, height: u32) -> (u32, u32) {
+    match side {
+        Sides::TOP => (idx, depth),
+        Sides::RIGHT => (width - 1 - depth, idx),
+        Sides::BOTTOM => (idx, height - 1 - depth),
+        Sides::LEFT => (depth, idx),
+    }
+}
+
+// pixels more transparent than the threshold are classed as Transparent rather than a palette color
+fn nearest_color(color: &Rgba<u8>, options: &ExtractOptions) -> String {
+    if color.0[3] < options.alpha_threshold {
+        return TRANSPARENT.to_string();
+    }
+    let rgb = Rgb([color.0[0], color.0[1], color.0[2]]);
+    options.palette.nearest(&rgb, options.metric).name.clone()
+}
+
+// majority vote across the band; ties go to the class seen closest to the edge
+fn classify_band(band: &[Rgba<u8>], options: &ExtractOptions) -> String {
+    let mut votes: Vec<(String, u32)> = Vec::new();
+    for px in band {
+        let col = nearest_color(px, options);
+        match votes.iter_mut().find(|v| v.0 == col) {
+            Some(v) => v.1 += 1,
+            None => votes.push((col, 1)),
+        }
+    }
+
+    let mut best = 0;
+    for (i, v) in votes.iter().enumerate() {
+        if v.1 > votes[best].1 {
+            best = i;
+        }
+    }
+    votes.swap_remove(best).0
+}
+
+// the class of every pixel position along each side, in SIDES order
+pub fn classify_sides(img: &RgbaImage, options: &ExtractOptions) -> Vec<Vec<String>> {
+    SIDES.iter().map(|side| {
+        SideIterator::new(*side, img, options.band).map(|band| classify_band(&band, options)).collect()
+    }).collect()
+}
+
+fn segment_stats(side: Sides, img: &RgbaImage, color: &str, start: u32, end: u32, options: &ExtractOptions) -> (Option<[u8; 3]>, f32) {
+    let mut sum = [0u64; 3];
+    let mut count = 0;
+    let mut opaque = 0;
+    let mut agreeing = 0;
+    for band in SideIterator::new(side, img, options.band).skip(start as usize).take((end - start) as usize) {
+        for px in band {
+            count += 1;
+            if nearest_color(&px, options) == color {
+                agreeing += 1;
+            }
+            if px.0[3] < options.alpha_threshold {
+                continue;
+            }
+            for (s, v) in sum.iter_mut().zip(px.0.iter()) {
+                *s += *v as u64;
+            }
+            opaque += 1;
+        }
+    }
+    let mean = if opaque > 0 { Some(sum.map(|s| (s / opaque) as u8)) } else { None };
+    (mean, agreeing as f32 / count as f32)
+}
+
+fn band_means(side: Sides, img: &RgbaImage, options: &ExtractOptions) -> Vec<[f32; 3]> {
+    SideIterator::new(side, img, options.band).map(|band| {
+        let mut mean = [0.0; 3];
+        for px in &band {
+            for (m, v) in mean.iter_mut().zip(px.0.iter()) {
+                *m += *v as f32 / band.len() as f32;
+            }
+        }
+        mean
+    }).collect()
+}
+
+fn mean_of(colors: &[[f32; 3]]) -> [f32; 3] {
+    let mut mean = [0.0; 3];
+    for c in colors {
+        for (m, v) in mean.iter_mut().zip(c.iter()) {
+            *m += v / colors.len() as f32;
+        }
+    }
+    mean
+}
+
+// the share of a pixel covered by `to`, judged by where its color lies on the line between two run colors
+fn coverage(px: &[f32; 3], from: &[f32; 3], to: &[f32; 3]) -> Option<f32> {
+    let d: Vec<f32> = (0..3).map(|c| to[c] - from[c]).collect();
+    let length_sq: f32 = d.iter().map(|v| v * v).sum();
+    if length_sq < 1.0 {
+        return None;
+    }
+    let t = (0..3).map(|c| (px[c] - from[c]) * d[c]).sum::<f32>() / length_sq;
+    Some(t.clamp(0.0, 1.0))
+}
+
+// Moves each boundary between runs to sub-pixel accuracy. The two pixels either side of a
+// boundary are usually antialiased blends, so their area is split between the runs by how
+// far their color lies from each run's interior color. Runs too short to have an interior
+// keep whole pixels.
+fn refine_boundaries(runs: &mut [Run], means: &[[f32; 3]]) {
+    let starts: Vec<usize> = runs.iter().scan(0, |pos, r| {
+        let start = *pos;
+        *pos += r.1 as usize;
+        Some(start)
+    }).collect();
+
+    for i in 0..runs.len().saturating_sub(1) {
+        let (a_start, b_start) = (starts[i], starts[i + 1]);
+        let b_end = starts.get(i + 2).copied().unwrap_or(means.len());
+        if b_start - a_start < 3 || b_end - b_start < 3 {
+            continue;
+        }
+        let a = mean_of(&means[a_start + 1..b_start - 1]);
+        let b = mean_of(&means[b_start + 1..b_end - 1]);
+        if let (Some(last_a), Some(first_b)) = (coverage(&means[b_start - 1], &a, &b), coverage(&means[b_start], &a, &b)) {
+            let shift = last_a - (1.0 - first_b);
+            runs[i].1 -= shift;
+            runs[i + 1].1 += shift;
+        }
+    }
+}
+
+// the quantized edge, what was measured for each of its runs, and the runs before quantizing
+pub fn build_side_info(side: Sides, img: &RgbaImage, classes: &[String], options: &ExtractOptions) -> (EdgeInfo, Vec<Segment>, Vec<Run>) {
+    let mut runs: Vec<Run> = Vec::new();
+    for col in classes {
+        match runs.last_mut() {
+            Some(run) if run.0 == *col => run.1 += 1.0,
+            _ => runs.push((col.clone(), 1.0)),
+        }
+    }
+
+    if options.subpixel {
+        refine_boundaries(&mut runs, &band_means(side, img, options));
+    }
+    let raw = runs.clone();
+
+    let mut edge = Vec::new();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut position = 0.0;
+    for ((color, length), units) in quantize_runs(runs, options.denom, options.min_run) {
+        position += length;
+        let end = position.round() as u32;
+        let (mean_rgb, confidence) = segment_stats(side, img, &color, start, end, options);
+        segments.push(Segment {
+            color: color.clone(),
+            start,
+            end,
+            mean_rgb,
+            confidence,
+        });
+        edge.push((color, units));
+        start = end;
+    }
+    (edge, segments, raw)
+}
+
+pub fn edge_complexity(segments: &[Segment], options: &ExtractOptions) -> EdgeComplexity {
+    let total: u32 = segments.iter().map(|s| s.end - s.start).sum();
+    let mut shares: Vec<(&str, u32)> = Vec::new();
+    for s in segments {
+        match shares.iter_mut().find(|c| c.0 == s.color) {
+            Some(c) => c.1 += s.end - s.start,
+            None => shares.push((&s.color, s.end - s.start)),
+        }
+    }
+    let entropy: f32 = shares.iter().map(|c| {
+        let p = c.1 as f32 / total as f32;
+        -p * p.log2()
+    }).sum();
+    let confidence: f32 = segments.iter().map(|s| s.confidence * (s.end - s.start) as f32).sum::<f32>() / total as f32;
+
+    let runs = segments.len() as u32;
+    let score = runs as f32 * (1.0 + entropy) / confidence;
+    EdgeComplexity {
+        runs,
+        entropy,
+        confidence,
+        score,
+        complex: options.complex_threshold.is_some_and(|t| score > t),
+    }
+}
+
+// the side split into `samples` equal stretches, each the mean Lab color of its opaque band
+// pixels, or None where most of them are transparent
+pub fn edge_profile(side: Sides, img: &RgbaImage, options: &ExtractOptions) -> Vec<Option<Lab>> {
+    let bands: Vec<Vec<Option<Lab>>> = SideIterator::new(side, img, options.band)
+        .map(|band| band.iter().map(|px| {
+            if px.0[3] < options.alpha_threshold {
+                None
+            } else {
+                Some(rgb_to_lab(&Rgb([px.0[0], px.0[1], px.0[2]])))
+            }
+        }).collect())
+        .collect();
+    let length = bands.len() as u32;
+
+    (0..options.profile_samples).map(|i| {
+        let start = i * length / options.profile_samples;
+        let end = ((i + 1) * length / options.profile_samples).max(start + 1).min(length);
+        let stretch: Vec<&Option<Lab>> = bands[start as usize..end as usize].iter().flatten().collect();
+        let pixels: Vec<&Lab> = stretch.iter().filter_map(|px| px.as_ref()).collect();
+        if pixels.len() * 2 <= stretch.len() {
+            return None;
+        }
+        let mut lab = [0.0; 3];
+        for px in &pixels {
+            for (v, p) in lab.iter_mut().zip(px.iter()) {
+                *v += p / pixels.len() as f32;
+            }
+        }
+        // one decimal is well below a visible difference and keeps the edge file small
+        Some(lab.map(|v| (v * 10.0).round() / 10.0))
+    }).collect()
+}
+
+pub fn edges_from_classes(id: &str, img: &RgbaImage, trim: Sided<u32>, classes: &[Vec<String>], options: &ExtractOptions) -> FlagEdges {
+    // sizes and segment spans are given in the source image, before the trim
+    let width = img.width() + trim.left + trim.right;
+    let height = img.height() + trim.top + trim.bottom;
+    let (top, mut top_segments, top_runs) = build_side_info(Sides::TOP, img, &classes[0], options);
+    let (right, mut right_segments, right_runs) = build_side_info(Sides::RIGHT, img, &classes[1], options);
+    let (bottom, mut bottom_segments, bottom_runs) = build_side_info(Sides::BOTTOM, img, &classes[2], options);
+    let (left, mut left_segments, left_runs) = build_side_info(Sides::LEFT, img, &classes[3], options);
+    for (segments, offset) in [(&mut top_segments, trim.left), (&mut bottom_segments, trim.left), (&mut right_segments, trim.top), (&mut left_segments, trim.top)] {
+        for segment in segments.iter_mut() {
+            segment.start += offset;
+            segment.end += offset;
+        }
+    }
+    FlagEdges {
+        id: id.to_string(),
+        top,
+        right,
+        bottom,
+        left,
+        width,
+        height,
+        aspect: img.width() as f32 / img.height() as f32,
+        trim,
+        runs: Sided {
+            top: top_runs,
+            right: right_runs,
+            bottom: bottom_runs,
+            left: left_runs,
+        },
+        complexity: Sided {
+            top: edge_complexity(&top_segments, options),
+            right: edge_complexity(&right_segments, options),
+            bottom: edge_complexity(&bottom_segments, options),
+            left: edge_complexity(&left_segments, options),
+        },
+        profiles: Sided {
+            top: edge_profile(Sides::TOP, img, options),
+            right: edge_profile(Sides::RIGHT, img, options),
+            bottom: edge_profile(Sides::BOTTOM, img, options),
+            left: edge_profile(Sides::LEFT, img, options),
+        },
+        segments: Sided {
+            top: top_segments,
+            right: right_segments,
+            bottom: bottom_segments,
+            left: left_segments,
+        },
+        metadata: None,
+    }
+}
+
+pub fn extract_edges(id: &str, img: &DynamicImage, options: &ExtractOptions) -> FlagEdges {
+    let (img, trim) = trim_frame(&img.to_rgba8(), options);
+    let classes = classify_sides(&img, options);
+    edges_from_classes(id, &img, trim, &classes, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [f32; 3] = [206.0, 17.0, 38.0];
+    const BLUE: [f32; 3] = [0.0, 70.0, 170.0];
+
+    // 10 red columns, one column blending red_share of red into blue, then 9 blue columns,
+    // so the true boundary lies at 10 + red_share pixels
+    fn split_flag(red_share: f32) -> RgbaImage {
+        RgbaImage::from_fn(20, 10, |x, _| {
+            let share = match x {
+                0..=9 => 1.0,
+                10 => red_share,
+                _ => 0.0,
+            };
+            let mix = |c: usize| (RED[c] * share + BLUE[c] * (1.0 - share)).round() as u8;
+            Rgba([mix(0), mix(1), mix(2), 255])
+        })
+    }
+
+    fn top_runs(img: &RgbaImage, options: &ExtractOptions) -> Vec<Run> {
+        let classes = classify_sides(img, options);
+        build_side_info(Sides::TOP, img, &classes[0], options).2
+    }
+
+    #[test]
+    fn boundaries_land_between_pixels() {
+        // the blended column is classified blue at 0.3 and red at 0.8
+        for red_share in [0.3, 0.8] {
+            let runs = top_runs(&split_flag(red_share), &ExtractOptions::default());
+            assert_eq!(runs.len(), 2);
+            assert_eq!((runs[0].0.as_str(), runs[1].0.as_str()), ("Red", "Blue"));
+            assert!((runs[0].1 - (10.0 + red_share)).abs() < 0.05, "{:?}", runs);
+            assert!((runs[0].1 + runs[1].1 - 20.0).abs() < 1e-4, "{:?}", runs);
+        }
+    }
+
+    #[test]
+    fn whole_pixels_without_subpixel() {
+        let options = ExtractOptions {
+            subpixel: false,
+            ..ExtractOptions::default()
+        };
+        let runs = top_runs(&split_flag(0.3), &options);
+        assert_eq!(runs.iter().map(|r| r.1).collect::<Vec<f32>>(), vec![10.0, 10.0]);
+    }
+
+    #[test]
+    fn transparent_pixels_stay_out_of_profiles() {
+        // samples of 5 pixels: all transparent black, 3 of 5 transparent, then white
+        let img = RgbaImage::from_fn(20, 10, |x, _| match x {
+            0..=7 => Rgba([0, 0, 0, 0]),
+            _ => Rgba([255, 255, 255, 255]),
+        });
+        let options = ExtractOptions {
+            profile_samples: 4,
+            ..ExtractOptions::default()
+        };
+        let white = Some([100.0, 0.0, 0.0]);
+        assert_eq!(edge_profile(Sides::TOP, &img, &options), vec![None, None, white, white]);
+    }
+
+    #[test]
+    fn transparent_pixels_stay_out_of_segment_colors() {
+        // transparent black on the left, and one transparent column in the right side's band
+        let img = RgbaImage::from_fn(20, 10, |x, _| match x {
+            0..=7 | 18 => Rgba([0, 0, 0, 0]),
+            _ => Rgba([128, 128, 128, 255]),
+        });
+        let options = ExtractOptions {
+            band: 3,
+            max_frame: 0,
+            ..ExtractOptions::default()
+        };
+        let edges = extract_edges("cut", &DynamicImage::ImageRgba8(img), &options);
+        let colors: Vec<Option<[u8; 3]>> = edges.segments.left.iter().chain(&edges.segments.right).map(|s| s.mean_rgb).collect();
+        assert_eq!(colors, vec![None, Some([128, 128, 128])]);
+    }
+
+    #[test]
+    fn padding_keeps_the_aspect_ratio() {
+        // the split flag with 4 pixels of white above and below
+        let flag = split_flag(0.3);
+        let padded = RgbaImage::from_fn(20, 18, |x, y| match y {
+            4..=13 => *flag.get_pixel(x, y - 4),
+            _ => Rgba([255, 255, 255, 255]),
+        });
+        let edges = extract_edges("split", &DynamicImage::ImageRgba8(padded), &ExtractOptions::default());
+        assert_eq!((edges.trim.top, edges.trim.bottom), (4, 4));
+        assert_eq!((edges.width, edges.height), (20, 18));
+        assert_eq!(edges.aspect, 2.0);
+        assert_eq!((edges.segments.left[0].start, edges.segments.left[0].end), (4, 14));
+    }
+}
@@ -0,0 +1,153 @@
+use image::{Rgba, RgbaImage};
+
+use crate::extract::{side_pixel_coords, ExtractOptions, SIDES};
+use crate::shared::*;
+
+// how far a channel may stray from the line's reference color and still count as uniform
+const CHANNEL_TOLERANCE: i32 = 32;
+// share of a line's pixels that must match for the line to count as uniform
+const UNIFORM_SHARE: f32 = 0.98;
+// a band only ends where at most this share of the next line still matches it
+const BAND_END_SHARE: f32 = 0.5;
+const NEAR_WHITE: u8 = 235;
+
+fn line_pixels(img: &RgbaImage, side: Sides, depth: u32) -> Vec<Rgba<u8>> {
+    let (width, height) = img.dimensions();
+    let length = match side {
+        Sides::TOP | Sides::BOTTOM => width,
+        Sides::LEFT | Sides::RIGHT => height,
+    };
+    (0..length).map(|idx| {
+        let (x, y) = side_pixel_coords(side, idx, depth, width, height);
+        *img.get_pixel(x, y)
+    }).collect()
+}
+
+fn share_matching(pixels: &[Rgba<u8>], color: &Rgba<u8>, options: &ExtractOptions) -> f32 {
+    pixels.iter().filter(|px| same_color(px, color, options)).count() as f32 / pixels.len() as f32
+}
+
+// the color of a side's outermost line, or None when the line isn't one color
+fn outer_color(img: &RgbaImage, side: Sides, options: &ExtractOptions) -> Option<Rgba<u8>> {
+    let pixels = line_pixels(img, side, 0);
+    let mut sum = [0u64; 4];
+    for px in &pixels {
+        for (s, v) in sum.iter_mut().zip(px.0.iter()) {
+            *s += *v as u64;
+        }
+    }
+    let mean = Rgba(sum.map(|s| (s / pixels.len() as u64) as u8));
+    if share_matching(&pixels, &mean, options) >= UNIFORM_SHARE {
+        Some(mean)
+    } else {
+        None
+    }
+}
+
+fn same_color(a: &Rgba<u8>, b: &Rgba<u8>, options: &ExtractOptions) -> bool {
+    let transparent = |px: &Rgba<u8>| px.0[3] < options.alpha_threshold;
+    if transparent(a) || transparent(b) {
+        return transparent(a) && transparent(b);
+    }
+    (0..3).all(|c| (a.0[c] as i32 - b.0[c] as i32).abs() <= CHANNEL_TOLERANCE)
+}
+
+fn is_background(color: &Rgba<u8>, options: &ExtractOptions) -> bool {
+    color.0[3] < options.alpha_threshold || color.0[..3].iter().all(|v| *v >= NEAR_WHITE)
+}
+
+// A uniform band along a side, if it ends sharply within max_frame pixels. Bands that keep
+// going or fade out gradually are part of the flag itself, such as a white field, a solid
+// border or the base of a triangle.
+fn uniform_band(img: &RgbaImage, side: Sides, options: &ExtractOptions) -> Option<(u32, Rgba<u8>)> {
+    let max_depth = match side {
+        Sides::TOP | Sides::BOTTOM => img.height(),
+        Sides::LEFT | Sides::RIGHT => img.width(),
+    };
+    let color = outer_color(img, side, options)?;
+    for depth in 1..=options.max_frame.min(max_depth / 4) {
+        let share = share_matching(&line_pixels(img, side, depth), &color, options);
+        if share <= BAND_END_SHARE {
+            return Some((depth, color));
+        } else if share < UNIFORM_SHARE {
+            return None;
+        }
+    }
+    None
+}
+
+// Trims whitespace or transparent padding from any side, and a frame of one color that
+// runs around all four sides. Returns the trimmed image and the pixels removed per side.
+pub fn trim_frame(img: &RgbaImage, options: &ExtractOptions) -> (RgbaImage, Sided<u32>) {
+    let mut img = img.clone();
+    let mut trim = Sided::default();
+    if options.max_frame == 0 {
+        return (img, trim);
+    }
+
+    // padding can surround a frame, so repeat until nothing more comes off
+    loop {
+        let bands: Vec<Option<(u32, Rgba<u8>)>> = SIDES.iter().map(|side| uniform_band(&img, *side, options)).collect();
+        let framed = bands.iter().all(|b| b.is_some_and(|b| bands[0].is_some_and(|first| same_color(&b.1, &first.1, options))));
+
+        let cut: Vec<u32> = bands.iter().map(|band| match band {
+            Some((depth, color)) if framed || is_background(color, options) => *depth,
+            _ => 0,
+        }).collect();
+        if cut.iter().all(|c| *c == 0) {
+            return (img, trim);
+        }
+
+        let (width, height) = img.dimensions();
+        img = image::imageops::crop_imm(&img, cut[3], cut[0], width - cut[1] - cut[3], height - cut[0] - cut[2]).to_image();
+        trim.top += cut[0];
+        trim.right += cut[1];
+        trim.bottom += cut[2];
+        trim.left += cut[3];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const RED: Rgba<u8> = Rgba([206, 17, 38, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 70, 170, 255]);
+
+    fn trims(img: &RgbaImage) -> (u32, u32, u32, u32, (u32, u32)) {
+        let (trimmed, trim) = trim_frame(img, &ExtractOptions::default());
+        (trim.top, trim.right, trim.bottom, trim.left, trimmed.dimensions())
+    }
+
+    #[test]
+    fn one_color_frame_is_trimmed() {
+        let img = RgbaImage::from_fn(60, 40, |x, y| {
+            if x < 2 || y < 2 || x >= 58 || y >= 38 {
+                Rgba([0, 0, 0, 255])
+            } else if x < 30 {
+                RED
+            } else {
+                BLUE
+            }
+        });
+        assert_eq!(trims(&img), (2, 2, 2, 2, (56, 36)));
+    }
+
+    #[test]
+    fn padding_is_trimmed_on_its_side_only() {
+        let img = RgbaImage::from_fn(60, 40, |x, y| match (x, y) {
+            (0..=2, _) => WHITE,
+            (_, 0..=19) => RED,
+            _ => BLUE,
+        });
+        assert_eq!(trims(&img), (0, 0, 0, 3, (57, 40)));
+    }
+
+    #[test]
+    fn wide_white_field_is_kept() {
+        // white over red, like the flag of Poland
+        let img = RgbaImage::from_fn(60, 40, |_, y| if y < 20 { WHITE } else { RED });
+        assert_eq!(trims(&img), (0, 0, 0, 0, (60, 40)));
+    }
+}
@@ -1,19 +1,176 @@
+pub mod color;
+pub mod extract;
+pub mod frame;
+pub mod matching;
+pub mod metadata;
+pub mod palette;
+pub mod raster;
+pub mod runs;
+
 pub mod shared {
     use serde::{Serialize, Deserialize};
     use std::collections::HashMap;
+    use crate::color::Lab;
+    use crate::matching::MatchSettings;
+    use crate::metadata::FlagMetadata;
+    use crate::runs::{quantize_edge, Run};
     use std::fmt;
 
     pub type EdgeInfo = Vec<(String, u32)>;
 
-    #[derive(Serialize, Deserialize, Debug)]
+    // class given to edge pixels that are (mostly) transparent
+    pub const TRANSPARENT: &str = "Transparent";
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct FlagEdges {
         pub id: String,
         pub top: EdgeInfo,
         pub right: EdgeInfo,
         pub bottom: EdgeInfo,
         pub left: EdgeInfo,
+        // size of the source image; the sampled flag is this less the trim
+        pub width: u32,
+        pub height: u32,
+        // of the sampled flag, so padding doesn't change its shape
+        pub aspect: f32,
+        // pixels of frame or padding removed from each side before sampling
+        pub trim: Sided<u32>,
+        // each side's runs before quantizing, with their lengths in (fractional) pixels, so they
+        // can be re-quantized at another denominator without reading the image again
+        pub runs: Sided<Vec<Run>>,
+        pub segments: Sided<Vec<Segment>>,
+        pub complexity: Sided<EdgeComplexity>,
+        // each side resampled to a fixed number of mean Lab colors, for similarity search;
+        // None where a sample is mostly transparent
+        pub profiles: Sided<Vec<Option<Lab>>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub metadata: Option<FlagMetadata>,
     }
-    
+
+    impl FlagEdges {
+        pub fn edge(&self, side: Sides) -> &EdgeInfo {
+            use Sides::*;
+            match side {
+                TOP => &self.top,
+                RIGHT => &self.right,
+                BOTTOM => &self.bottom,
+                LEFT => &self.left,
+            }
+        }
+
+        pub fn edge_mut(&mut self, side: Sides) -> &mut EdgeInfo {
+            use Sides::*;
+            match side {
+                TOP => &mut self.top,
+                RIGHT => &mut self.right,
+                BOTTOM => &mut self.bottom,
+                LEFT => &mut self.left,
+            }
+        }
+
+        // "name (id)" when the flag has metadata, otherwise just the id
+        pub fn display_name(&self) -> String {
+            match &self.metadata {
+                Some(metadata) => format!("{} ({})", metadata.name, self.id),
+                None => self.id.clone(),
+            }
+        }
+
+        // rebuilds the edges from the raw runs; with colors_only every run counts as one unit,
+        // so only the order of colors is compared
+        pub fn requantize(&mut self, denom: Option<u32>, min_run: f32, colors_only: bool) {
+            for side in [Sides::TOP, Sides::RIGHT, Sides::BOTTOM, Sides::LEFT] {
+                if let Some(denom) = denom {
+                    *self.edge_mut(side) = quantize_edge(self.runs.side(side), denom, min_run);
+                }
+                if colors_only {
+                    for run in self.edge_mut(side) {
+                        run.1 = 1;
+                    }
+                }
+            }
+        }
+    }
+
+    // one value for each side of a flag
+    #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct Sided<T> {
+        pub top: T,
+        pub right: T,
+        pub bottom: T,
+        pub left: T,
+    }
+
+    impl<T> Sided<T> {
+        pub fn side(&self, side: Sides) -> &T {
+            use Sides::*;
+            match side {
+                TOP => &self.top,
+                RIGHT => &self.right,
+                BOTTOM => &self.bottom,
+                LEFT => &self.left,
+            }
+        }
+
+        pub fn side_mut(&mut self, side: Sides) -> &mut T {
+            use Sides::*;
+            match side {
+                TOP => &mut self.top,
+                RIGHT => &mut self.right,
+                BOTTOM => &mut self.bottom,
+                LEFT => &mut self.left,
+            }
+        }
+    }
+
+    // what was measured for one run of an edge; start and end are pixel positions along the
+    // side of the source image (end exclusive) and confidence is the share of sampled pixels
+    // that took its color; mean_rgb leaves out transparent pixels and is None if all of them are
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct Segment {
+        pub color: String,
+        pub start: u32,
+        pub end: u32,
+        pub mean_rgb: Option<[u8; 3]>,
+        pub confidence: f32,
+    }
+
+    // describes the settings that produced an edge file
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct EdgeSource {
+        pub palette: String,
+        pub palette_file: Option<String>,
+        pub metric: String,
+        pub denom: u32,
+        pub band: u32,
+        pub min_run: f32,
+        pub alpha_threshold: u8,
+        pub complex_threshold: Option<f32>,
+        pub profile_samples: u32,
+        pub max_frame: u32,
+        pub subpixel: bool,
+        // coarse class of each palette shade that has one
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        pub classes: HashMap<String, String>,
+    }
+
+    // how hard an edge is to match: score grows with the number of runs and the color entropy
+    // (in bits) along the edge, and shrinks with the mean classification confidence
+    #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct EdgeComplexity {
+        pub runs: u32,
+        pub entropy: f32,
+        pub confidence: f32,
+        pub score: f32,
+        pub complex: bool,
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct EdgeFile {
+        pub source: EdgeSource,
+        pub flags: Vec<FlagEdges>,
+    }
+
     #[derive(Debug, Copy, Clone)]
     pub enum Sides {
         TOP,
@@ -33,6 +190,16 @@ pub mod shared {
             }
         }
 
+        pub fn name(&self) -> &'static str {
+            use Sides::*;
+            match self {
+                TOP => "top",
+                RIGHT => "right",
+                BOTTOM => "bottom",
+                LEFT => "left"
+            }
+        }
+
         pub fn offset(&self) -> (i32, i32) {
             use Sides::*;
             match self {
@@ -44,7 +211,21 @@ pub mod shared {
         }
     }
 
-    
+    impl std::str::FromStr for Sides {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            use Sides::*;
+            match s.to_lowercase().as_str() {
+                "top" => Ok(TOP),
+                "right" => Ok(RIGHT),
+                "bottom" => Ok(BOTTOM),
+                "left" => Ok(LEFT),
+                _ => Err(format!("unknown side {}, expected top, right, bottom or left", s)),
+            }
+        }
+    }
+
     #[derive(Clone, Debug)]
     pub struct FlagGraph {
         pub graph: HashMap<(i32, i32), String>,
@@ -57,6 +238,9 @@ pub mod shared {
         graph: Vec<((i32, i32), String)>,
         remaining_flags: HashMap<String, u32>,
         idx: u32,
+        // how the matcher compared edges when it saved the graph
+        #[serde(default)]
+        pub matching: MatchSettings,
     }
 
     impl FlagGraphSerialize {
@@ -65,6 +249,7 @@ pub mod shared {
                 graph: flag_graph.graph.iter().map(|v| ((v.0.0, v.0.1), v.1.to_string())).collect(),
                 remaining_flags: flag_graph.remaining_flags.clone(),
                 idx: flag_graph.idx,
+                matching: MatchSettings::default(),
             }
         }
 
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::shared::{EdgeInfo, FlagEdges, TRANSPARENT};
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransparentMode {
+    // Transparent only matches Transparent, like any other color
+    Strict,
+    // Transparent matches any color across the same stretch
+    Wildcard,
+}
+
+impl TransparentMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransparentMode::Strict => "strict",
+            TransparentMode::Wildcard => "wildcard",
+        }
+    }
+}
+
+impl FromStr for TransparentMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(TransparentMode::Strict),
+            "wildcard" => Ok(TransparentMode::Wildcard),
+            _ => Err(format!("unknown transparent mode {}, expected strict or wildcard", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComplexMode {
+    // complex edges never match, so nothing is placed against them
+    Unmatchable,
+    // complex edges match any edge
+    Wildcard,
+    // complex edges only match the same complex edge
+    Same,
+}
+
+impl ComplexMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ComplexMode::Unmatchable => "unmatchable",
+            ComplexMode::Wildcard => "wildcard",
+            ComplexMode::Same => "same",
+        }
+    }
+}
+
+impl FromStr for ComplexMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "unmatchable" => Ok(ComplexMode::Unmatchable),
+            "wildcard" => Ok(ComplexMode::Wildcard),
+            "same" => Ok(ComplexMode::Same),
+            _ => Err(format!("unknown complex mode {}, expected unmatchable, wildcard or same", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchOn {
+    // colors only match the same palette shade
+    Shade,
+    // colors match any shade of the same class
+    Class,
+}
+
+impl MatchOn {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MatchOn::Shade => "shade",
+            MatchOn::Class => "class",
+        }
+    }
+}
+
+impl FromStr for MatchOn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "shade" => Ok(MatchOn::Shade),
+            "class" => Ok(MatchOn::Class),
+            _ => Err(format!("unknown match mode {}, expected shade or class", s)),
+        }
+    }
+}
+
+// the coarse class of a shade, or the shade itself if it has none
+pub fn class<'a>(classes: &'a HashMap<String, String>, color: &'a str) -> &'a str {
+    classes.get(color).map_or(color, |c| c.as_str())
+}
+
+// one color per unit, so edges can be compared position by position
+pub fn edge_units(edge: &EdgeInfo) -> Vec<String> {
+    edge.iter().flat_map(|segment| std::iter::repeat_n(segment.0.clone(), segment.1 as usize)).collect()
+}
+
+// how the matcher compares edges; saved with every graph so it can be checked the same way
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatchSettings {
+    pub transparent: TransparentMode,
+    pub complex: ComplexMode,
+    pub match_on: MatchOn,
+    // edges are re-quantized from their raw runs into this many units before matching
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub denom: Option<u32>,
+    // every run counts as one unit, so only the order of colors is compared
+    #[serde(default)]
+    pub colors_only: bool,
+}
+
+// the matcher's defaults, which graphs saved before the settings were recorded were made with
+impl Default for MatchSettings {
+    fn default() -> Self {
+        MatchSettings {
+            transparent: TransparentMode::Strict,
+            complex: ComplexMode::Same,
+            match_on: MatchOn::Shade,
+            denom: None,
+            colors_only: false,
+        }
+    }
+}
+
+impl MatchSettings {
+    pub fn requantize(&self, flag: &mut FlagEdges, min_run: f32) {
+        if self.denom.is_some() || self.colors_only {
+            flag.requantize(self.denom, min_run, self.colors_only);
+        }
+    }
+
+    // whether edges that differ can still match, unit by unit
+    pub fn inexact(&self) -> bool {
+        self.transparent == TransparentMode::Wildcard || self.match_on == MatchOn::Class
+    }
+
+    pub fn colors_match(&self, x: &str, y: &str, classes: &HashMap<String, String>) -> bool {
+        x == y
+            || (self.transparent == TransparentMode::Wildcard && (x == TRANSPARENT || y == TRANSPARENT))
+            || (self.match_on == MatchOn::Class && class(classes, x) == class(classes, y))
+    }
+
+    // whether two edges may touch, given whether edge-gen marked each of them complex
+    pub fn edges_match(&self, a: &EdgeInfo, a_complex: bool, b: &EdgeInfo, b_complex: bool, classes: &HashMap<String, String>) -> bool {
+        if a_complex || b_complex {
+            return match self.complex {
+                ComplexMode::Unmatchable => false,
+                ComplexMode::Wildcard => true,
+                ComplexMode::Same => a_complex == b_complex && a == b,
+            };
+        }
+        if a == b {
+            return true;
+        }
+        if !self.inexact() {
+            return false;
+        }
+        let (a, b) = (edge_units(a), edge_units(b));
+        a.len() == b.len() && a.iter().zip(&b).all(|(x, y)| self.colors_match(x, y, classes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(units: &[(&str, u32)]) -> EdgeInfo {
+        units.iter().map(|(color, units)| (color.to_string(), *units)).collect()
+    }
+
+    #[test]
+    fn shades_match_by_class_only_when_asked() {
+        let classes = HashMap::from([("Navy".to_string(), "Blue".to_string())]);
+        let (a, b) = (edge(&[("Navy", 12), ("Red", 12)]), edge(&[("Blue", 12), ("Red", 12)]));
+        assert!(!MatchSettings::default().edges_match(&a, false, &b, false, &classes));
+        let class = MatchSettings { match_on: MatchOn::Class, ..MatchSettings::default() };
+        assert!(class.edges_match(&a, false, &b, false, &classes));
+        assert!(!class.edges_match(&a, false, &edge(&[("Blue", 8), ("Red", 16)]), false, &classes));
+    }
+
+    #[test]
+    fn transparent_wildcards_cover_any_color() {
+        let classes = HashMap::new();
+        let (a, b) = (edge(&[("Transparent", 12), ("Red", 12)]), edge(&[("Blue", 6), ("White", 6), ("Red", 12)]));
+        assert!(!MatchSettings::default().edges_match(&a, false, &b, false, &classes));
+        let wildcard = MatchSettings { transparent: TransparentMode::Wildcard, ..MatchSettings::default() };
+        assert!(wildcard.edges_match(&a, false, &b, false, &classes));
+    }
+
+    #[test]
+    fn complex_edges_follow_the_complex_mode() {
+        let classes = HashMap::new();
+        let (a, b) = (edge(&[("Red", 24)]), edge(&[("Blue", 24)]));
+        let mode = |complex| MatchSettings { complex, ..MatchSettings::default() };
+        assert!(mode(ComplexMode::Same).edges_match(&a, true, &a, true, &classes));
+        assert!(!mode(ComplexMode::Same).edges_match(&a, true, &a, false, &classes));
+        assert!(!mode(ComplexMode::Unmatchable).edges_match(&a, true, &a, true, &classes));
+        assert!(mode(ComplexMode::Wildcard).edges_match(&a, true, &b, false, &classes));
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{Serialize, Deserialize};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+// human readable information about a flag, keyed by flag id in the metadata file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlagMetadata {
+    pub name: String,
+    pub region: String,
+    // id of the state the flag's territory belongs to; a sovereign state names itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sovereignty: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+pub fn load_metadata<P: AsRef<Path>>(path: P) -> HashMap<String, FlagMetadata> {
+    let path = path.as_ref();
+    let mut contents = String::new();
+    File::open(path).unwrap().read_to_string(&mut contents).unwrap();
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&contents).unwrap(),
+        _ => serde_json::from_str(&contents).unwrap(),
+    }
+}
@@ -0,0 +1,169 @@
+use image::Rgb;
+use serde::{Serialize, Deserialize};
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use crate::color::*;
+use crate::shared::TRANSPARENT;
+
+pub type Pixel = Rgb<u8>;
+
+const BLACK_THRESH: u8 = 75;
+
+// reference values are typical flag shades rather than pure primaries, so
+// that perceptual metrics place e.g. dark greens nearer Green than Black
+const COLORS: [(&str, Pixel); 9] = [
+    ("Red", Rgb([206, 17, 38])),
+    ("Green", Rgb([0, 122, 61])),
+    ("Blue", Rgb([0, 70, 170])),
+    ("Yellow", Rgb([252, 209, 22])),
+    ("Cyan", Rgb([120, 190, 235])),
+    ("Magenta", Rgb([190, 40, 180])),
+    ("Orange", Rgb([255, 136, 0])),
+    ("White", Rgb([255, 255, 255])),
+    ("Black", Rgb([0, 0, 0])),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PaletteColor {
+    pub name: String,
+    pub rgb: [u8; 3],
+    // maximum distance (in units of the active metric) at which this color is chosen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    // coarser color this shade counts as when matching on class, e.g. Navy under Blue;
+    // a color without one is its own class
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(skip)]
+    lab: Lab,
+}
+
+impl PaletteColor {
+    pub fn new(name: &str, rgb: [u8; 3]) -> PaletteColor {
+        PaletteColor {
+            name: name.to_string(),
+            rgb,
+            tolerance: None,
+            aliases: Vec::new(),
+            class: None,
+            lab: rgb_to_lab(&Rgb(rgb)),
+        }
+    }
+
+    pub fn pixel(&self) -> Pixel {
+        Rgb(self.rgb)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<PaletteColor>,
+}
+
+impl Palette {
+    pub fn builtin() -> Palette {
+        Palette {
+            name: String::from("default"),
+            colors: COLORS.iter().map(|c| PaletteColor::new(c.0, c.1.0)).collect(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Palette {
+        let path = path.as_ref();
+        let mut contents = String::new();
+        File::open(path).unwrap().read_to_string(&mut contents).unwrap();
+
+        let mut palette: Palette = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&contents).unwrap(),
+            _ => serde_json::from_str(&contents).unwrap(),
+        };
+        assert!(!palette.colors.is_empty(), "palette {} has no colors", path.display());
+
+        let mut names = HashSet::new();
+        for c in &palette.colors {
+            for name in std::iter::once(&c.name).chain(c.aliases.iter()) {
+                assert!(names.insert(name.clone()), "palette {} defines {} twice", path.display(), name);
+                assert!(name != TRANSPARENT, "palette {} may not define {}, it is reserved", path.display(), name);
+            }
+            assert!(c.class.as_deref() != Some(TRANSPARENT), "palette {} may not use {} as a class", path.display(), TRANSPARENT);
+        }
+
+        for c in &mut palette.colors {
+            c.lab = rgb_to_lab(&c.pixel());
+        }
+        palette
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) {
+        let path = path.as_ref();
+        let contents = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::to_string(self).unwrap(),
+            _ => serde_json::to_string_pretty(self).unwrap(),
+        };
+        File::create(path).unwrap().write_all(contents.as_bytes()).unwrap();
+    }
+
+    // the class of every color that is grouped under another name
+    pub fn classes(&self) -> HashMap<String, String> {
+        self.colors.iter().filter_map(|c| Some((c.name.clone(), c.class.clone()?))).collect()
+    }
+
+    // finds a color by its name or one of its aliases
+    pub fn resolve(&self, name: &str) -> Option<&PaletteColor> {
+        self.colors.iter().find(|c| c.name == name || c.aliases.iter().any(|a| a == name))
+    }
+
+    pub fn nearest(&self, color: &Pixel, metric: ColorMetric) -> &PaletteColor {
+        match metric {
+            ColorMetric::Rgb => self.nearest_rgb(color),
+            ColorMetric::Cie76 | ColorMetric::Ciede2000 => self.nearest_lab(color, metric),
+        }
+    }
+
+    // colors whose tolerance excludes the pixel are skipped, unless every color does
+    fn pick<F: Fn(&PaletteColor) -> f32>(&self, distance: F) -> &PaletteColor {
+        let mut best: Option<(&PaletteColor, f32)> = None;
+        let mut best_any: Option<(&PaletteColor, f32)> = None;
+        for c in &self.colors {
+            let d = distance(c);
+            if best_any.is_none() || d < best_any.unwrap().1 {
+                best_any = Some((c, d));
+            }
+            let within = c.tolerance.is_none_or(|t| d <= t);
+            if within && (best.is_none() || d < best.unwrap().1) {
+                best = Some((c, d));
+            }
+        }
+        best.or(best_any).unwrap().0
+    }
+
+    fn nearest_rgb(&self, color: &Pixel) -> &PaletteColor {
+        let not_black = color[0] > BLACK_THRESH || color[1] > BLACK_THRESH || color[2] > BLACK_THRESH;
+        self.pick(|c| {
+            if c.name == "Black" && not_black {
+                return f32::MAX;
+            }
+            let mut d_sq = 0.0;
+            for i in 0..3 {
+                d_sq += ((color[i] as f32) - (c.rgb[i] as f32)).powi(2);
+            }
+            d_sq.sqrt()
+        })
+    }
+
+    // in Lab space black is just another palette entry, so no threshold is needed
+    fn nearest_lab(&self, color: &Pixel, metric: ColorMetric) -> &PaletteColor {
+        let lab = rgb_to_lab(color);
+        self.pick(|c| match metric {
+            ColorMetric::Ciede2000 => delta_e2000(&lab, &c.lab),
+            _ => delta_e76(&lab, &c.lab),
+        })
+    }
+}
@@ -0,0 +1,39 @@
+use image::{DynamicImage, RgbaImage};
+use resvg::{tiny_skia, usvg};
+
+use std::path::Path;
+
+pub fn is_svg<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) == Some(String::from("svg"))
+}
+
+// renders an SVG at exactly width x height; a missing height keeps the document's aspect ratio
+pub fn rasterize_svg<P: AsRef<Path>>(path: P, width: u32, height: Option<u32>) -> DynamicImage {
+    let data = std::fs::read(path.as_ref()).unwrap();
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_data(&data, &options).unwrap();
+
+    let size = tree.size();
+    let height = height.unwrap_or_else(|| (width as f32 * size.height() / size.width()).round().max(1.0) as u32);
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).unwrap();
+    let transform = tiny_skia::Transform::from_scale(width as f32 / size.width(), height as f32 / size.height());
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia stores premultiplied alpha
+    let mut img = RgbaImage::new(width, height);
+    for (px, out) in pixmap.pixels().iter().zip(img.pixels_mut()) {
+        let c = px.demultiply();
+        *out = image::Rgba([c.red(), c.green(), c.blue(), c.alpha()]);
+    }
+    DynamicImage::ImageRgba8(img)
+}
+
+// opens a raster image, or renders an SVG svg_width pixels wide
+pub fn open_image<P: AsRef<Path>>(path: P, svg_width: u32) -> DynamicImage {
+    if is_svg(&path) {
+        rasterize_svg(path, svg_width, None)
+    } else {
+        image::open(path).unwrap()
+    }
+}
@@ -0,0 +1,147 @@
+use crate::shared::EdgeInfo;
+
+// a run of one classified color along an edge, with its length in pixels
+pub type Run = (String, f32);
+
+// joins neighbouring runs that ended up with the same color
+fn coalesce(runs: &mut Vec<Run>) {
+    let mut i = 1;
+    while i < runs.len() {
+        if runs[i].0 == runs[i - 1].0 {
+            let run = runs.remove(i);
+            runs[i - 1].1 += run.1;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+// gives the run at idx to its longer neighbour
+fn merge_into_neighbour(runs: &mut Vec<Run>, idx: usize) {
+    let before = if idx > 0 { Some(idx - 1) } else { None };
+    let after = if idx + 1 < runs.len() { Some(idx + 1) } else { None };
+    let target = match (before, after) {
+        (Some(b), Some(a)) => if runs[a].1 > runs[b].1 { a } else { b },
+        (Some(b), None) => b,
+        (None, Some(a)) => a,
+        (None, None) => return,
+    };
+    let length = runs[idx].1;
+    runs[target].1 += length;
+    runs.remove(idx);
+    coalesce(runs);
+}
+
+fn shortest(runs: &[Run], filter: impl Fn(usize) -> bool) -> Option<usize> {
+    (0..runs.len())
+        .filter(|&i| filter(i))
+        .min_by(|&a, &b| runs[a].1.partial_cmp(&runs[b].1).unwrap())
+}
+
+// largest remainder allocation, so the proportions always sum to denom
+fn allocate(runs: &[Run], denom: u32) -> Vec<u32> {
+    let total: f32 = runs.iter().map(|r| r.1).sum();
+    let quotas: Vec<f32> = runs.iter().map(|r| r.1 * denom as f32 / total).collect();
+    let mut props: Vec<u32> = quotas.iter().map(|q| q.floor() as u32).collect();
+
+    let mut order: Vec<usize> = (0..runs.len()).collect();
+    order.sort_by(|&a, &b| {
+        let rem_a = quotas[a] - quotas[a].floor();
+        let rem_b = quotas[b] - quotas[b].floor();
+        rem_b.partial_cmp(&rem_a).unwrap().then(quotas[b].partial_cmp(&quotas[a]).unwrap()).then(a.cmp(&b))
+    });
+
+    let assigned: u32 = props.iter().sum();
+    for &i in order.iter().take((denom - assigned) as usize) {
+        props[i] += 1;
+    }
+    props
+}
+
+// runs shorter than min_run units of 1/denom are merged into a neighbour rather than dropped;
+// returns the merged runs with the units allocated to each
+pub fn quantize_runs(mut runs: Vec<Run>, denom: u32, min_run: f32) -> Vec<(Run, u32)> {
+    assert!(denom > 0, "edges must be divided into at least one unit");
+    coalesce(&mut runs);
+    let total: f32 = runs.iter().map(|r| r.1).sum();
+
+    while let Some(i) = shortest(&runs, |_| runs.len() > 1) {
+        if runs[i].1 * denom as f32 / total >= min_run {
+            break;
+        }
+        merge_into_neighbour(&mut runs, i);
+    }
+
+    loop {
+        let props = allocate(&runs, denom);
+        match shortest(&runs, |i| runs.len() > 1 && props[i] == 0) {
+            Some(i) => merge_into_neighbour(&mut runs, i),
+            None => return runs.into_iter().zip(props).collect(),
+        }
+    }
+}
+
+// quantizes raw runs straight to an edge, dropping the merged lengths
+pub fn quantize_edge(runs: &[Run], denom: u32, min_run: f32) -> EdgeInfo {
+    quantize_runs(runs.to_vec(), denom, min_run).into_iter().map(|((color, _), units)| (color, units)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runs(lengths: &[(&str, f32)]) -> Vec<Run> {
+        lengths.iter().map(|(color, length)| (color.to_string(), *length)).collect()
+    }
+
+    fn edge(units: &[(&str, u32)]) -> EdgeInfo {
+        units.iter().map(|(color, units)| (color.to_string(), *units)).collect()
+    }
+
+    #[test]
+    fn allocations_sum_to_denom() {
+        let raw = runs(&[("A", 3.7), ("B", 10.1), ("C", 5.2), ("D", 0.9), ("E", 7.3)]);
+        for denom in [1, 2, 5, 12, 24, 48, 100] {
+            let quantized = quantize_runs(raw.clone(), denom, 0.5);
+            assert_eq!(quantized.iter().map(|(_, units)| units).sum::<u32>(), denom, "denom {}", denom);
+            assert!(quantized.iter().all(|(_, units)| *units > 0), "denom {}", denom);
+        }
+    }
+
+    #[test]
+    fn ties_go_to_the_earlier_run() {
+        let raw = runs(&[("A", 1.0), ("B", 1.0), ("C", 1.0)]);
+        assert_eq!(quantize_edge(&raw, 4, 0.5), edge(&[("A", 2), ("B", 1), ("C", 1)]));
+    }
+
+    #[test]
+    fn ties_in_remainder_go_to_the_larger_quota() {
+        // quotas 1.5 and 2.5 tie on remainder
+        let raw = runs(&[("A", 3.0), ("B", 5.0)]);
+        assert_eq!(quantize_edge(&raw, 4, 0.5), edge(&[("A", 1), ("B", 3)]));
+    }
+
+    #[test]
+    fn short_runs_merge_into_a_neighbour() {
+        let raw = runs(&[("A", 100.0), ("B", 1.0), ("C", 100.0)]);
+        assert_eq!(quantize_edge(&raw, 24, 1.0), edge(&[("A", 12), ("C", 12)]));
+    }
+
+    #[test]
+    fn merged_runs_coalesce_with_matching_colors() {
+        let raw = runs(&[("A", 100.0), ("B", 1.0), ("A", 100.0)]);
+        assert_eq!(quantize_runs(raw, 24, 1.0), vec![(("A".to_string(), 201.0), 24)]);
+    }
+
+    #[test]
+    fn a_single_run_takes_every_unit() {
+        assert_eq!(quantize_edge(&runs(&[("A", 5.0)]), 7, 0.5), edge(&[("A", 7)]));
+        assert_eq!(quantize_edge(&runs(&[("A", 5.0)]), 1, 2.0), edge(&[("A", 1)]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn a_zero_denom_panics() {
+        quantize_runs(runs(&[("A", 1.0), ("B", 1.0)]), 0, 0.5);
+    }
+}